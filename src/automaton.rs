@@ -0,0 +1,229 @@
+use std::io;
+use value::ValueReader;
+use {Reader, common_prefix_len};
+
+/// Deterministic automaton over the bytes of the keys, used to search an
+/// sstable with `Table::search`.
+///
+/// Regular expressions, Levenshtein automata or prefixes can all be
+/// expressed this way.
+pub trait Automaton {
+    type State: Clone;
+
+    /// State before any byte has been fed.
+    fn start(&self) -> Self::State;
+
+    /// Returns `true` if a key ending in this state is accepted.
+    fn accept(&self, state: &Self::State) -> bool;
+
+    /// Returns `false` if no key going through this state can be accepted,
+    /// whatever its remaining bytes.
+    fn can_match(&self, state: &Self::State) -> bool;
+
+    /// Returns the state reached by feeding `byte`.
+    fn transition(&self, state: &Self::State, byte: u8) -> Self::State;
+}
+
+/// Returns the smallest key greater than all of the keys starting with
+/// `prefix`, or `None` if there is none.
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut successor = prefix.to_vec();
+    while successor.last() == Some(&255u8) {
+        successor.pop();
+    }
+    let last_byte = successor.last_mut()?;
+    *last_byte += 1;
+    Some(successor)
+}
+
+/// Reader over the keys accepted by an automaton.
+///
+/// Automaton states are kept for each byte of the last key, so that a key
+/// only feeds the automaton the bytes it does not share with the previous
+/// key. When no key with a given prefix can be accepted, the reader seeks
+/// past all of them.
+pub struct SearchReader<'a, TValueReader, A: Automaton> {
+    reader: Reader<'a, TValueReader>,
+    automaton: A,
+    /// `states[i]` is the state reached after feeding `prefix[..i]`.
+    states: Vec<A::State>,
+    prefix: Vec<u8>,
+    /// `true` if the reader was moved by `seek` onto a key that has not been
+    /// processed yet. Keys may have been skipped, so the prefix shared with
+    /// the previous key is not meaningful.
+    skipped: bool,
+}
+
+impl<'a, TValueReader, A> SearchReader<'a, TValueReader, A>
+    where TValueReader: ValueReader, A: Automaton {
+
+    pub fn new(reader: Reader<'a, TValueReader>, automaton: A) -> SearchReader<'a, TValueReader, A> {
+        let start = automaton.start();
+        SearchReader {
+            reader,
+            automaton,
+            states: vec![start],
+            prefix: Vec::new(),
+            skipped: false,
+        }
+    }
+
+    fn last_state(&self) -> &A::State {
+        self.states.last().expect("there is always a start state")
+    }
+
+    pub fn advance(&mut self) -> io::Result<bool> {
+        if !self.automaton.can_match(&self.states[0]) {
+            return Ok(false);
+        }
+        loop {
+            if !self.skipped && !self.reader.advance()? {
+                return Ok(false);
+            }
+            let common_prefix_len = if self.skipped {
+                common_prefix_len(&self.prefix[..], self.reader.key())
+            } else {
+                self.reader.delta_reader.common_prefix_len().min(self.prefix.len())
+            };
+            self.skipped = false;
+            self.prefix.truncate(common_prefix_len);
+            self.states.truncate(common_prefix_len + 1);
+            let mut can_match = self.automaton.can_match(self.last_state());
+            for &byte in &self.reader.key()[common_prefix_len..] {
+                if !can_match {
+                    break;
+                }
+                let state = self.automaton.transition(self.last_state(), byte);
+                can_match = self.automaton.can_match(&state);
+                self.states.push(state);
+                self.prefix.push(byte);
+            }
+            if can_match {
+                if self.automaton.accept(self.last_state()) {
+                    return Ok(true);
+                }
+                continue;
+            }
+            // No key starting with `prefix` can match.
+            let successor = if let Some(successor) = prefix_successor(&self.prefix[..]) {
+                successor
+            } else {
+                return Ok(false);
+            };
+            if !self.reader.seek(&successor[..])? {
+                return Ok(false);
+            }
+            self.skipped = true;
+        }
+    }
+
+    pub fn key(&self) -> &[u8] {
+        self.reader.key()
+    }
+
+    pub fn value(&self) -> &TValueReader::Value {
+        self.reader.value()
+    }
+
+    /// Returns the ordinal of the current key.
+    pub fn ord(&self) -> u64 {
+        self.reader.ord()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::io::Cursor;
+    use {SSTable, U64SSTable, WriterOptions};
+    use super::{Automaton, prefix_successor};
+
+    /// Accepts the keys within `max_distance` edits of `query`.
+    struct Levenshtein {
+        query: Vec<u8>,
+        max_distance: usize,
+        num_transitions: Cell<usize>,
+    }
+
+    impl Automaton for Levenshtein {
+        type State = Vec<usize>;
+
+        fn start(&self) -> Vec<usize> {
+            (0..self.query.len() + 1).collect()
+        }
+
+        fn accept(&self, state: &Vec<usize>) -> bool {
+            state[self.query.len()] <= self.max_distance
+        }
+
+        fn can_match(&self, state: &Vec<usize>) -> bool {
+            state.iter().any(|&distance| distance <= self.max_distance)
+        }
+
+        fn transition(&self, state: &Vec<usize>, byte: u8) -> Vec<usize> {
+            self.num_transitions.set(self.num_transitions.get() + 1);
+            let mut next = vec![state[0] + 1];
+            for (i, &query_byte) in self.query.iter().enumerate() {
+                let cost = if query_byte == byte { 0 } else { 1 };
+                let distance = (state[i] + cost).min(state[i + 1] + 1).min(next[i] + 1);
+                next.push(distance);
+            }
+            next
+        }
+    }
+
+    fn levenshtein_distance(left: &[u8], right: &[u8]) -> usize {
+        let automaton = Levenshtein { query: left.to_vec(), max_distance: 0, num_transitions: Cell::new(0) };
+        let state = right.iter().fold(automaton.start(), |state, &byte| automaton.transition(&state, byte));
+        state[left.len()]
+    }
+
+    #[test]
+    fn test_prefix_successor() {
+        assert_eq!(prefix_successor(b"abc"), Some(b"abd".to_vec()));
+        assert_eq!(prefix_successor(b"ab\xff\xff"), Some(b"ac".to_vec()));
+        assert_eq!(prefix_successor(b"\xff"), None);
+        assert_eq!(prefix_successor(b""), None);
+    }
+
+    #[test]
+    fn test_search() {
+        let keys: Vec<String> = (0..50_000u64)
+            .map(|i| format!("{:x}", i.wrapping_mul(2_654_435_761) % 1_000_003))
+            .collect::<::std::collections::BTreeSet<String>>()
+            .into_iter()
+            .collect();
+        let mut buffer = Vec::new();
+        {
+            let options = WriterOptions { block_size: 4_096, ..WriterOptions::default() };
+            let mut writer = U64SSTable::writer_with_options(&mut buffer, options);
+            for (ord, key) in keys.iter().enumerate() {
+                writer.write(key.as_bytes(), &(ord as u64)).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        let table = U64SSTable::table(Cursor::new(&buffer[..])).unwrap();
+        for query in &["abcde", "f00", "12345", "ffff"] {
+            let automaton = Levenshtein {
+                query: query.as_bytes().to_vec(),
+                max_distance: 1,
+                num_transitions: Cell::new(0),
+            };
+            let mut search = table.search(automaton).unwrap();
+            let mut found = Vec::new();
+            while search.advance().unwrap() {
+                assert_eq!(search.ord(), *search.value());
+                found.push(String::from_utf8(search.key().to_vec()).unwrap());
+            }
+            let expected: Vec<String> = keys.iter()
+                .filter(|key| levenshtein_distance(query.as_bytes(), key.as_bytes()) <= 1)
+                .cloned()
+                .collect();
+            assert!(!expected.is_empty());
+            assert_eq!(found, expected);
+            let num_bytes: usize = keys.iter().map(|key| key.len()).sum();
+            assert!(search.automaton.num_transitions.get() < num_bytes / 10);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+/// Returns the number of bits required to represent `val`.
+pub fn num_bits(val: u64) -> u8 {
+    (64 - val.leading_zeros()) as u8
+}
+
+/// Returns the number of bytes used by `num_vals` values of `num_bits` bits,
+/// or `None` if it overflows.
+pub fn num_bytes(num_vals: usize, num_bits: u8) -> Option<usize> {
+    num_vals.checked_mul(usize::from(num_bits)).map(|num_bits| num_bits.div_ceil(8))
+}
+
+/// Appends `vals` to `output`, using `num_bits` bits per value.
+///
+/// All values are expected to fit on `num_bits` bits.
+pub fn pack(vals: &[u64], num_bits: u8, output: &mut Vec<u8>) {
+    let mut acc = 0u128;
+    let mut acc_bits = 0u32;
+    for &val in vals {
+        debug_assert!(self::num_bits(val) <= num_bits);
+        acc |= u128::from(val) << acc_bits;
+        acc_bits += u32::from(num_bits);
+        while acc_bits >= 8 {
+            output.push(acc as u8);
+            acc >>= 8;
+            acc_bits -= 8;
+        }
+    }
+    if acc_bits > 0 {
+        output.push(acc as u8);
+    }
+}
+
+/// Decodes `num_vals` values of `num_bits` bits from `data` and
+/// appends them to `output`.
+///
+/// `data` must contain at least `num_bytes(num_vals, num_bits)` bytes.
+pub fn unpack(data: &[u8], num_vals: usize, num_bits: u8, output: &mut Vec<u64>) {
+    let mask = if num_bits == 64 { u64::MAX } else { (1u64 << num_bits) - 1 };
+    let num_bits = u32::from(num_bits);
+    let mut acc = 0u128;
+    let mut acc_bits = 0u32;
+    let mut bytes = data.iter();
+    for _ in 0..num_vals {
+        while acc_bits < num_bits {
+            acc |= u128::from(*bytes.next().unwrap()) << acc_bits;
+            acc_bits += 8;
+        }
+        output.push(acc as u64 & mask);
+        acc >>= num_bits;
+        acc_bits -= num_bits;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{num_bits, num_bytes, pack, unpack};
+
+    fn aux_test_pack(vals: &[u64]) {
+        let num_bits = vals.iter().cloned().map(num_bits).max().unwrap_or(0);
+        let mut buffer = Vec::new();
+        pack(vals, num_bits, &mut buffer);
+        assert_eq!(Some(buffer.len()), num_bytes(vals.len(), num_bits));
+        let mut output = Vec::new();
+        unpack(&buffer[..], vals.len(), num_bits, &mut output);
+        assert_eq!(&output[..], vals);
+    }
+
+    #[test]
+    fn test_num_bits() {
+        assert_eq!(num_bits(0), 0);
+        assert_eq!(num_bits(1), 1);
+        assert_eq!(num_bits(255), 8);
+        assert_eq!(num_bits(256), 9);
+        assert_eq!(num_bits(u64::MAX), 64);
+    }
+
+    #[test]
+    fn test_pack() {
+        aux_test_pack(&[]);
+        aux_test_pack(&[0, 0, 0]);
+        aux_test_pack(&[1, 0, 1, 1, 0]);
+        aux_test_pack(&[3, 1_000, 7, 123_456]);
+        aux_test_pack(&[u64::MAX, 1, u64::MAX - 1]);
+        aux_test_pack(&(0..1_000).map(|i| i * 13).collect::<Vec<u64>>());
+    }
+}
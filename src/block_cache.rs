@@ -0,0 +1,235 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+static NEXT_TABLE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Returns an id identifying a table within the block caches.
+pub(crate) fn next_table_id() -> u64 {
+    NEXT_TABLE_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Table id and offset of a block.
+type BlockKey = (u64, u64);
+
+struct LruState {
+    /// Blocks, with the time they were last used.
+    blocks: HashMap<BlockKey, (u64, Arc<[u8]>)>,
+    /// Keys of the blocks, by time of last use.
+    recency: BTreeMap<u64, BlockKey>,
+    time: u64,
+    num_bytes: usize,
+}
+
+impl LruState {
+
+    fn touch(&mut self, key: BlockKey) -> Option<Arc<[u8]>> {
+        self.time += 1;
+        let time = self.time;
+        let &mut (ref mut last_use, ref block) = self.blocks.get_mut(&key)?;
+        self.recency.remove(last_use);
+        self.recency.insert(time, key);
+        *last_use = time;
+        Some(block.clone())
+    }
+
+    fn remove(&mut self, key: BlockKey) {
+        if let Some((last_use, block)) = self.blocks.remove(&key) {
+            self.recency.remove(&last_use);
+            self.num_bytes -= block.len();
+        }
+    }
+}
+
+/// Cache of decompressed blocks, shared between tables and their readers.
+///
+/// Blocks are identified by the table they belong to and by their offset.
+/// Once the blocks take more than `capacity` bytes, the least recently used
+/// ones are evicted.
+///
+/// A cache is attached to a table with `Table::with_block_cache` or
+/// `MmapTable::with_block_cache`. It is then used by point lookups and by
+/// the readers of the table, forward and reverse.
+pub struct BlockCache {
+    capacity: usize,
+    state: Mutex<LruState>,
+    num_hits: AtomicU64,
+    num_misses: AtomicU64,
+}
+
+impl BlockCache {
+
+    pub fn new(capacity: usize) -> BlockCache {
+        BlockCache {
+            capacity,
+            state: Mutex::new(LruState {
+                blocks: HashMap::new(),
+                recency: BTreeMap::new(),
+                time: 0,
+                num_bytes: 0,
+            }),
+            num_hits: AtomicU64::new(0),
+            num_misses: AtomicU64::new(0),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, LruState> {
+        // The state is consistent between calls, even if one of them panicked.
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Maximum number of bytes of the cached blocks.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of bytes of the cached blocks.
+    pub fn num_bytes(&self) -> usize {
+        self.state().num_bytes
+    }
+
+    /// Number of blocks that were found in the cache.
+    pub fn num_hits(&self) -> u64 {
+        self.num_hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of blocks that were not found in the cache, and had to be read.
+    pub fn num_misses(&self) -> u64 {
+        self.num_misses.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn get(&self, table_id: u64, offset: u64) -> Option<Arc<[u8]>> {
+        let block = self.state().touch((table_id, offset));
+        if block.is_some() {
+            self.num_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.num_misses.fetch_add(1, Ordering::Relaxed);
+        }
+        block
+    }
+
+    /// Caches a block, unless it is larger than the capacity of the cache.
+    pub(crate) fn insert(&self, table_id: u64, offset: u64, block: Arc<[u8]>) {
+        if block.len() > self.capacity {
+            return;
+        }
+        let key = (table_id, offset);
+        let mut state = self.state();
+        state.remove(key);
+        state.time += 1;
+        let time = state.time;
+        state.num_bytes += block.len();
+        state.blocks.insert(key, (time, block));
+        state.recency.insert(time, key);
+        while state.num_bytes > self.capacity {
+            let (_, lru_key) = state.recency.pop_first().expect("cached blocks are not empty");
+            state.remove(lru_key);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::io::Cursor;
+    use std::ops::Bound;
+    use std::sync::Arc;
+    use {SSTable, U64SSTable};
+    use table::tests::{key, small_block_u64_table};
+    use super::BlockCache;
+
+    fn block(len: usize) -> Arc<[u8]> {
+        vec![0u8; len].into()
+    }
+
+    #[test]
+    fn test_block_cache_lru() {
+        let block_cache = BlockCache::new(100);
+        assert!(block_cache.get(0, 0).is_none());
+        block_cache.insert(0, 0, block(40));
+        block_cache.insert(0, 40, block(40));
+        block_cache.insert(1, 0, block(10));
+        assert_eq!(block_cache.num_bytes(), 90);
+        assert_eq!(block_cache.get(0, 0).map(|block| block.len()), Some(40));
+        block_cache.insert(1, 10, block(30));
+        assert_eq!(block_cache.num_bytes(), 80);
+        assert!(block_cache.get(0, 40).is_none());
+        assert!(block_cache.get(0, 0).is_some());
+        assert!(block_cache.get(1, 0).is_some());
+        block_cache.insert(1, 0, block(20));
+        assert_eq!(block_cache.num_bytes(), 90);
+        block_cache.insert(2, 0, block(101));
+        assert!(block_cache.get(2, 0).is_none());
+        assert_eq!(block_cache.num_hits(), 3);
+        assert_eq!(block_cache.num_misses(), 3);
+    }
+
+    #[test]
+    fn test_table_block_cache() {
+        let buffer = small_block_u64_table();
+        let block_cache = Arc::new(BlockCache::new(1_000_000));
+        let table = U64SSTable::table(Cursor::new(&buffer[..])).unwrap()
+            .with_block_cache(block_cache.clone());
+        let other_table = U64SSTable::table(Cursor::new(&buffer[..])).unwrap()
+            .with_block_cache(block_cache.clone());
+        assert!(table.index().blocks().len() > 10);
+        let num_blocks = (1_000..1_010u64)
+            .map(|i| table.index().search(key(i).as_bytes()))
+            .collect::<BTreeSet<_>>()
+            .len() as u64;
+        for _ in 0..2 {
+            for i in 1_000..1_010u64 {
+                assert_eq!(table.get(key(i).as_bytes()).unwrap(), Some(i));
+                assert_eq!(table.key_to_ord(key(i).as_bytes()).unwrap(), Some(i));
+                assert_eq!(table.ord_to_key(i).unwrap(), Some(key(i).into_bytes()));
+            }
+        }
+        assert_eq!(block_cache.num_misses(), num_blocks);
+        assert_eq!(block_cache.num_hits(), 60 - num_blocks);
+        assert_eq!(other_table.get(key(1_000).as_bytes()).unwrap(), Some(1_000));
+        assert_eq!(block_cache.num_misses(), num_blocks + 1);
+        assert_eq!(table.get(key(19_999).as_bytes()).unwrap(), Some(19_999));
+        assert_eq!(block_cache.num_misses(), num_blocks + 2);
+        assert!(block_cache.num_bytes() > 0);
+    }
+
+    #[test]
+    fn test_table_readers_block_cache() {
+        let buffer = small_block_u64_table();
+        let block_cache = Arc::new(BlockCache::new(10_000_000));
+        let table = U64SSTable::table(&buffer[..]).unwrap()
+            .with_block_cache(block_cache.clone());
+        let num_blocks = table.index().blocks().len() as u64;
+        for _ in 0..2 {
+            let mut reader = table.range(Bound::Included(key(1_000).as_bytes()), Bound::Unbounded).unwrap();
+            for i in 1_000..20_000u64 {
+                assert!(reader.advance().unwrap());
+                assert_eq!(reader.value(), &i);
+            }
+            assert!(!reader.advance().unwrap());
+        }
+        let first_block_id = table.index().search(key(1_000).as_bytes()).unwrap() as u64;
+        assert_eq!(block_cache.num_misses(), num_blocks - first_block_id);
+        assert_eq!(block_cache.num_hits(), num_blocks - first_block_id);
+        let mut reader = table.reverse_range(Bound::Unbounded, Bound::Unbounded).unwrap();
+        for i in (0..20_000u64).rev() {
+            assert!(reader.advance().unwrap());
+            assert_eq!(reader.value(), &i);
+        }
+        assert!(!reader.advance().unwrap());
+        assert_eq!(block_cache.num_misses(), num_blocks);
+        let mut reader = table.prefix(b"key0004").unwrap();
+        assert!(reader.seek(key(15_000).as_bytes()).unwrap());
+        assert_eq!(reader.value(), &15_000);
+        let mut reader = U64SSTable::table(&buffer[..]).unwrap()
+            .with_block_cache(block_cache.clone())
+            .into_range(Bound::Unbounded, Bound::Unbounded).unwrap();
+        for i in 0..20_000u64 {
+            assert!(reader.advance().unwrap());
+            assert_eq!(reader.ord(), i);
+        }
+        assert!(!reader.advance().unwrap());
+        assert_eq!(block_cache.num_misses(), 2 * num_blocks);
+    }
+}
@@ -1,34 +1,320 @@
-use std::io;
-use super::BLOCK_LEN;
-use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{self, Read};
+use std::ops::Range;
+use std::sync::Arc;
+use super::{BLOCK_HEADER_LEN, BLOCK_CHECKSUM_LEN, BLOCK_CHECKSUM_FLAG};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
+use crc32c;
+use codec;
+use error::CorruptionError;
+use read_at::{ReadAt, ReadAtReader};
+
+/// In-memory sstable, from which blocks are borrowed rather than copied.
+enum Bytes<'a> {
+    Borrowed(&'a [u8]),
+    Shared(Arc<[u8]>),
+    /// Source whose `as_bytes` is not `None`, such as a memory mapped file.
+    Owned(Box<dyn ReadAt + 'a>),
+}
+
+impl<'a> Bytes<'a> {
+    fn as_slice(&self) -> &[u8] {
+        match *self {
+            Bytes::Borrowed(data) => data,
+            Bytes::Shared(ref data) => &data[..],
+            Bytes::Owned(ref source) => source.as_bytes().unwrap_or(&[]),
+        }
+    }
+}
+
+/// Returns the block `block_id`, already checked and decompressed, with the
+/// number of bytes it takes in the sstable, or `None` past the last block.
+pub(crate) type LoadBlock<'a> = Box<dyn FnMut(usize) -> io::Result<Option<(Arc<[u8]>, u64)>> + 'a>;
+
+enum Source<'a> {
+    Read(Box<dyn io::Read + 'a>),
+    /// Storage read at the offset of the next block, which skips blocks
+    /// without reading them.
+    ReadAt(ReadAtReader<Box<dyn ReadAt + 'a>>),
+    /// Only `bytes[position..end]` remains to be read.
+    Bytes { bytes: Bytes<'a>, position: usize, end: usize },
+    /// Blocks loaded one at a time, such as from a `BlockCache`. `block`
+    /// is the current one.
+    Blocks { load_block: LoadBlock<'a>, block: Option<Arc<[u8]>> },
+}
+
+impl<'a> Source<'a> {
+
+    /// Consumes the next `num_bytes` bytes of an in-memory source,
+    /// and returns their range.
+    fn take_range(&mut self, num_bytes: u64) -> io::Result<Range<usize>> {
+        match *self {
+            Source::Bytes { ref mut position, end: bytes_end, .. } => {
+                let start = *position;
+                let end = (start as u64).checked_add(num_bytes)
+                    .filter(|&end| end <= bytes_end as u64)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "sstable is truncated"))?;
+                *position = end as usize;
+                Ok(start..end as usize)
+            }
+            Source::Read(_) | Source::ReadAt(_) | Source::Blocks { .. } => unreachable!("only in-memory sources have ranges"),
+        }
+    }
+
+    /// Returns the source as an `io::Read`, unless it is held in memory.
+    fn reader(&mut self) -> Option<&mut (dyn io::Read + 'a)> {
+        match *self {
+            Source::Read(ref mut reader) => Some(reader),
+            Source::ReadAt(ref mut reader) => Some(reader),
+            Source::Bytes { .. } | Source::Blocks { .. } => None,
+        }
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        if let Some(reader) = self.reader() {
+            return reader.read_u32::<LittleEndian>();
+        }
+        let range = self.take_range(4)?;
+        Ok(LittleEndian::read_u32(&self.bytes()[range]))
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match *self {
+            Source::Bytes { ref bytes, .. } => bytes.as_slice(),
+            Source::Read(_) | Source::ReadAt(_) | Source::Blocks { .. } => &[],
+        }
+    }
+}
+
+/// Returns the current block, with its codec id.
+///
+/// It lives in `raw_block` if it was read from an `io::Read`,
+/// and is borrowed from the source otherwise.
+fn raw_block<'b>(source: &'b Source, raw_block: &'b [u8], raw_range: &Range<usize>) -> &'b [u8] {
+    match *source {
+        Source::Read(_) | Source::ReadAt(_) | Source::Blocks { .. } => raw_block,
+        Source::Bytes { ref bytes, .. } => &bytes.as_slice()[raw_range.clone()],
+    }
+}
 
 pub struct BlockReader<'a> {
+    raw_block: Vec<u8>,
+    raw_range: Range<usize>,
     buffer: Vec<u8>,
-    reader: Box<io::Read + 'a>
+    compressed: bool,
+    source: Source<'a>,
+    terminated: bool,
+    next_block_id: usize,
+    next_block_offset: u64,
 }
 
 impl<'a> BlockReader<'a> {
 
-    pub fn new(reader: Box<io::Read + 'a>) -> BlockReader<'a> {
+    fn with_source(source: Source<'a>) -> BlockReader<'a> {
         BlockReader {
-            buffer: Vec::with_capacity(BLOCK_LEN),
-            reader
+            raw_block: Vec::new(),
+            raw_range: 0..0,
+            buffer: Vec::new(),
+            compressed: false,
+            source,
+            terminated: false,
+            next_block_id: 0,
+            next_block_offset: 0,
+        }
+    }
+
+    pub fn new(reader: Box<dyn io::Read + 'a>) -> BlockReader<'a> {
+        BlockReader::with_source(Source::Read(reader))
+    }
+
+    /// Reads the `num_bytes` bytes of `source` starting at `offset`, which
+    /// the block reader takes ownership of.
+    pub(crate) fn from_read_at(source: Box<dyn ReadAt + 'a>, offset: u64, num_bytes: u64) -> BlockReader<'a> {
+        BlockReader::with_source(Source::ReadAt(ReadAtReader::new(source, offset, num_bytes)))
+    }
+
+    /// Reads the blocks of an sstable held in memory.
+    ///
+    /// Uncompressed blocks are borrowed from `data` rather than copied.
+    pub fn from_bytes(data: &'a [u8]) -> BlockReader<'a> {
+        BlockReader::with_source(Source::Bytes { bytes: Bytes::Borrowed(data), position: 0, end: data.len() })
+    }
+
+    /// Same as `from_bytes`, for an sstable in a shared buffer. The block
+    /// reader can then be `'static`.
+    pub fn from_shared_bytes(data: Arc<[u8]>) -> BlockReader<'a> {
+        let end = data.len();
+        BlockReader::with_source(Source::Bytes { bytes: Bytes::Shared(data), position: 0, end })
+    }
+
+    /// Reads the blocks within `range` of an sstable held in memory by
+    /// `source`, which the block reader takes ownership of.
+    pub(crate) fn from_owned_bytes(source: Box<dyn ReadAt + 'a>, range: Range<usize>) -> BlockReader<'a> {
+        let len = source.as_bytes().map(|data| data.len()).unwrap_or(0);
+        let end = range.end.min(len);
+        let position = range.start.min(end);
+        BlockReader::with_source(Source::Bytes { bytes: Bytes::Owned(source), position, end })
+    }
+
+    /// Reads the blocks returned by `load_block`, starting with the block
+    /// declared with `set_position`.
+    pub(crate) fn from_blocks(load_block: LoadBlock<'a>) -> BlockReader<'a> {
+        BlockReader::with_source(Source::Blocks { load_block, block: None })
+    }
+
+    /// Declares the ordinal and the offset of the next block to be read,
+    /// for readers that do not start at the beginning of the file.
+    ///
+    /// They are only used to report corruption.
+    pub(crate) fn set_position(&mut self, block_id: usize, block_offset: u64) {
+        self.next_block_id = block_id;
+        self.next_block_offset = block_offset;
+    }
+
+    /// Ordinal of the next block to be read.
+    pub(crate) fn next_block_id(&self) -> usize {
+        self.next_block_id
+    }
+
+    /// Offset of the next block to be read.
+    pub(crate) fn next_block_offset(&self) -> u64 {
+        self.next_block_offset
+    }
+
+    /// Discards the next `num_bytes` bytes without decoding them, and
+    /// declares the ordinal of the block that follows.
+    ///
+    /// `num_bytes` is expected to span whole blocks.
+    pub(crate) fn skip(&mut self, num_bytes: u64, block_id: usize) -> io::Result<()> {
+        if let Source::Blocks { ref mut block, .. } = self.source {
+            *block = None;
+        } else if let Source::ReadAt(ref mut reader) = self.source {
+            reader.skip(num_bytes)?;
+        } else if let Source::Read(ref mut reader) = self.source {
+            let num_bytes_skipped = io::copy(&mut reader.take(num_bytes), &mut io::sink())?;
+            if num_bytes_skipped != num_bytes {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "sstable is truncated"));
+            }
+        } else {
+            self.source.take_range(num_bytes)?;
         }
+        self.raw_block.clear();
+        self.raw_range = 0..0;
+        self.compressed = false;
+        self.next_block_id = block_id;
+        self.next_block_offset += num_bytes;
+        Ok(())
     }
 
+    /// Loads the next block into the buffer, and checks its checksum if it has one.
+    ///
+    /// Returns `false` once the zero-length block marking the end of
+    /// the blocks has been reached. Nothing is read after it.
     pub fn read_block(&mut self) -> io::Result<bool> {
-        let block_len = self.reader.read_u32::<LittleEndian>()?;
+        if self.terminated {
+            return Ok(false);
+        }
+        if let Source::Blocks { ref mut load_block, ref mut block } = self.source {
+            self.compressed = false;
+            if let Some((loaded_block, num_bytes)) = load_block(self.next_block_id)? {
+                *block = Some(loaded_block);
+                self.next_block_id += 1;
+                self.next_block_offset += num_bytes;
+                return Ok(true);
+            }
+            *block = None;
+            self.terminated = true;
+            return Ok(false);
+        }
+        let block_len = self.source.read_u32()?;
         if block_len == 0u32 {
-            self.buffer.clear();
-            Ok(false)
+            self.raw_block.clear();
+            self.raw_range = 0..0;
+            self.compressed = false;
+            self.terminated = true;
+            return Ok(false);
+        }
+        if let Some(reader) = self.source.reader() {
+            self.raw_block.resize(block_len as usize, 0u8);
+            reader.read_exact(&mut self.raw_block[..])?;
         } else {
-            self.buffer.resize(block_len as usize, 0u8);
-            self.reader.read_exact(&mut self.buffer[..])?;
-            Ok(true)
+            self.raw_range = self.source.take_range(u64::from(block_len))?;
+        }
+        let mut num_bytes = BLOCK_HEADER_LEN + u64::from(block_len);
+        let header = raw_block(&self.source, &self.raw_block, &self.raw_range)[0];
+        if header & BLOCK_CHECKSUM_FLAG != 0u8 {
+            let checksum = self.source.read_u32()?;
+            if crc32c::crc32c(raw_block(&self.source, &self.raw_block, &self.raw_range)) != checksum {
+                return Err(CorruptionError {
+                    block_id: Some(self.next_block_id),
+                    offset: self.next_block_offset,
+                }.into());
+            }
+            num_bytes += BLOCK_CHECKSUM_LEN;
         }
+        let codec_id = header & !BLOCK_CHECKSUM_FLAG;
+        self.compressed = codec_id != codec::NO_COMPRESSION_ID;
+        if self.compressed {
+            self.buffer.clear();
+            let data = &raw_block(&self.source, &self.raw_block, &self.raw_range)[1..];
+            codec::decompress(codec_id, data, &mut self.buffer)?;
+        }
+        self.next_block_id += 1;
+        self.next_block_offset += num_bytes;
+        Ok(true)
     }
 
+    /// Returns the decompressed content of the current block.
     pub fn buffer(&self) -> &[u8] {
-        &self.buffer
+        if self.compressed {
+            return &self.buffer[..];
+        }
+        if let Source::Blocks { ref block, .. } = self.source {
+            return block.as_ref().map(|block| &block[..]).unwrap_or(&[]);
+        }
+        let raw_block = raw_block(&self.source, &self.raw_block, &self.raw_range);
+        if raw_block.is_empty() {
+            &[]
+        } else {
+            &raw_block[1..]
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use {SSTable, VoidSSTable};
+    use super::BlockReader;
+
+    fn aux_test_from_bytes(data: &[u8], mut block_reader: BlockReader) {
+        let mut reader = BlockReader::new(Box::new(data));
+        let data_range = data.as_ptr_range();
+        let mut num_blocks = 0;
+        while reader.read_block().unwrap() {
+            assert!(block_reader.read_block().unwrap());
+            assert_eq!(block_reader.buffer(), reader.buffer());
+            assert!(data_range.contains(&block_reader.buffer().as_ptr()));
+            num_blocks += 1;
+        }
+        assert!(!block_reader.read_block().unwrap());
+        assert!(num_blocks > 1);
+        assert!(block_reader.read_block().is_ok());
+    }
+
+    #[test]
+    fn test_block_reader_from_bytes() {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = VoidSSTable::writer(&mut buffer);
+            for i in 0..100_000 {
+                writer.write(format!("key{:08}", i).as_bytes(), &()).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        aux_test_from_bytes(&buffer[..], BlockReader::from_bytes(&buffer[..]));
+        let shared: Arc<[u8]> = Arc::from(&buffer[..]);
+        aux_test_from_bytes(&shared[..], BlockReader::from_shared_bytes(shared.clone()));
+        assert!(BlockReader::from_bytes(&buffer[..10]).read_block().is_err());
     }
 }
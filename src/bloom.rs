@@ -0,0 +1,133 @@
+use std::io;
+
+/// Settings of the bloom filter built by the writer.
+///
+/// The filter is sized upfront, so that building it takes a bounded
+/// amount of memory regardless of the number of keys actually written.
+#[derive(Clone, Copy, Debug)]
+pub struct BloomFilterOptions {
+    /// Number of keys the filter is sized for.
+    pub expected_num_keys: u64,
+    /// Target probability for `may_contain` to return `true` for an absent
+    /// key, once `expected_num_keys` keys have been inserted.
+    pub false_positive_rate: f64,
+}
+
+/// Filters have at least this many bits.
+const MIN_NUM_BITS: usize = 64;
+
+/// Filters use at most this many hash functions.
+const MAX_NUM_HASHES: u8 = 30;
+
+/// Probabilistic set of keys, with no false negatives.
+///
+/// Serialized as the number of hash functions (u8) followed by the bitset.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BloomFilter {
+    num_hashes: u8,
+    bits: Vec<u8>,
+}
+
+fn hash(key: &[u8]) -> u64 {
+    // FNV-1a, followed by the splitmix64 finalizer to spread the bits.
+    let mut h = 0xcbf2_9ce4_8422_2325u64;
+    for &b in key {
+        h ^= u64::from(b);
+        h = h.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    h ^= h >> 30;
+    h = h.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h ^= h >> 27;
+    h = h.wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+impl BloomFilter {
+
+    pub fn new(options: BloomFilterOptions) -> BloomFilter {
+        let num_keys = options.expected_num_keys.max(1) as f64;
+        let false_positive_rate = options.false_positive_rate.clamp(1e-9, 0.5);
+        let ln2 = ::std::f64::consts::LN_2;
+        let num_bits = (-num_keys * false_positive_rate.ln() / (ln2 * ln2)).ceil().max(MIN_NUM_BITS as f64);
+        let num_hashes = (num_bits / num_keys * ln2).round().clamp(1.0, f64::from(MAX_NUM_HASHES));
+        BloomFilter {
+            num_hashes: num_hashes as u8,
+            bits: vec![0u8; (num_bits as usize).div_ceil(8)],
+        }
+    }
+
+    /// Positions of the bits associated with `key`.
+    ///
+    /// They are derived from a single hash, by double hashing.
+    fn bit_positions(&self, key: &[u8]) -> impl Iterator<Item=usize> {
+        let h = hash(key);
+        let num_bits = self.bits.len() as u64 * 8;
+        let h1 = h & 0xffff_ffff;
+        let h2 = (h >> 32) | 1;
+        (0..u64::from(self.num_hashes))
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+
+    pub fn insert(&mut self, key: &[u8]) {
+        for bit in self.bit_positions(key) {
+            self.bits[bit / 8] |= 1u8 << (bit % 8);
+        }
+    }
+
+    /// Returns `false` if `key` was definitely not inserted.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.bit_positions(key)
+            .all(|bit| self.bits[bit / 8] & (1u8 << (bit % 8)) != 0)
+    }
+
+    pub fn serialize(&self, output: &mut Vec<u8>) {
+        output.push(self.num_hashes);
+        output.extend_from_slice(&self.bits[..]);
+    }
+
+    /// Deserializes a filter, rejecting the ones the writer cannot produce.
+    ///
+    /// The content of the filter is covered by the checksum of the sections
+    /// of the sstable, which is checked before.
+    pub fn deserialize(data: &[u8]) -> io::Result<BloomFilter> {
+        if data.len() < 1 + MIN_NUM_BITS / 8 || data[0] == 0 || data[0] > MAX_NUM_HASHES {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid bloom filter"));
+        }
+        Ok(BloomFilter {
+            num_hashes: data[0],
+            bits: data[1..].to_vec(),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{BloomFilter, BloomFilterOptions};
+
+    #[test]
+    fn test_bloom_filter() {
+        let options = BloomFilterOptions { expected_num_keys: 10_000, false_positive_rate: 0.01 };
+        let mut filter = BloomFilter::new(options);
+        for i in 0..10_000 {
+            filter.insert(format!("key{}", i).as_bytes());
+        }
+        for i in 0..10_000 {
+            assert!(filter.may_contain(format!("key{}", i).as_bytes()));
+        }
+        let num_false_positives = (0..10_000)
+            .filter(|i| filter.may_contain(format!("absent{}", i).as_bytes()))
+            .count();
+        assert!(num_false_positives < 200, "{} false positives", num_false_positives);
+        let mut buffer = Vec::new();
+        filter.serialize(&mut buffer);
+        assert_eq!(BloomFilter::deserialize(&buffer[..]).unwrap(), filter);
+        assert!(BloomFilter::deserialize(&buffer[..1]).is_err());
+        assert!(BloomFilter::deserialize(&buffer[..8]).is_err());
+        let mut invalid_num_hashes = buffer.clone();
+        invalid_num_hashes[0] = 31;
+        assert!(BloomFilter::deserialize(&invalid_num_hashes[..]).is_err());
+        invalid_num_hashes[0] = 0;
+        assert!(BloomFilter::deserialize(&invalid_num_hashes[..]).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use std::io;
+#[cfg(feature = "lz4")]
+use lz4_flex;
+#[cfg(feature = "zstd")]
+use zstd;
+
+/// Identifier of `NoCompression`.
+pub const NO_COMPRESSION_ID: u8 = 0u8;
+/// Identifier of `Lz4`.
+pub const LZ4_ID: u8 = 1u8;
+/// Identifier of `Zstd`.
+pub const ZSTD_ID: u8 = 2u8;
+
+mod private {
+    /// Prevents `BlockCodec` from being implemented outside of this crate.
+    pub trait Sealed {}
+}
+
+/// Compression applied to each block when it is flushed.
+///
+/// The id of the codec is written at the start of every block, so that
+/// readers can decompress blocks without being told which codec was used.
+///
+/// The trait is sealed: readers only know how to decompress the built-in
+/// codecs, `NoCompression`, `Lz4` and `Zstd`.
+pub trait BlockCodec: private::Sealed {
+    /// Identifier recorded with each block. It is unique among codecs,
+    /// and lower than 128: the highest bit flags checksummed blocks.
+    fn id(&self) -> u8;
+
+    /// Appends the compressed version of `data` to `output`.
+    fn compress(&self, data: &[u8], output: &mut Vec<u8>) -> io::Result<()>;
+
+    /// Appends the decompressed version of `data` to `output`.
+    fn decompress(&self, data: &[u8], output: &mut Vec<u8>) -> io::Result<()>;
+}
+
+/// Stores blocks as is.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoCompression;
+
+impl private::Sealed for NoCompression {}
+
+impl BlockCodec for NoCompression {
+    fn id(&self) -> u8 {
+        NO_COMPRESSION_ID
+    }
+
+    fn compress(&self, data: &[u8], output: &mut Vec<u8>) -> io::Result<()> {
+        output.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn decompress(&self, data: &[u8], output: &mut Vec<u8>) -> io::Result<()> {
+        output.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+/// LZ4 block compression.
+#[cfg(feature = "lz4")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lz4;
+
+#[cfg(feature = "lz4")]
+impl private::Sealed for Lz4 {}
+
+#[cfg(feature = "lz4")]
+impl BlockCodec for Lz4 {
+    fn id(&self) -> u8 {
+        LZ4_ID
+    }
+
+    fn compress(&self, data: &[u8], output: &mut Vec<u8>) -> io::Result<()> {
+        output.extend_from_slice(&lz4_flex::compress_prepend_size(data));
+        Ok(())
+    }
+
+    fn decompress(&self, data: &[u8], output: &mut Vec<u8>) -> io::Result<()> {
+        let decompressed = lz4_flex::decompress_size_prepended(data)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        output.extend_from_slice(&decompressed);
+        Ok(())
+    }
+}
+
+/// Zstd block compression.
+#[cfg(feature = "zstd")]
+#[derive(Clone, Copy, Debug)]
+pub struct Zstd {
+    /// Compression level. It is not needed to decompress.
+    pub level: i32,
+}
+
+#[cfg(feature = "zstd")]
+impl Default for Zstd {
+    fn default() -> Zstd {
+        Zstd { level: zstd::DEFAULT_COMPRESSION_LEVEL }
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl private::Sealed for Zstd {}
+
+#[cfg(feature = "zstd")]
+impl BlockCodec for Zstd {
+    fn id(&self) -> u8 {
+        ZSTD_ID
+    }
+
+    fn compress(&self, data: &[u8], output: &mut Vec<u8>) -> io::Result<()> {
+        zstd::stream::copy_encode(data, output, self.level)
+    }
+
+    fn decompress(&self, data: &[u8], output: &mut Vec<u8>) -> io::Result<()> {
+        zstd::stream::copy_decode(data, output)
+    }
+}
+
+/// Decompresses a block written with the codec identified by `codec_id`.
+///
+/// Fails if the codec is unknown, or if the feature enabling it is disabled.
+pub(crate) fn decompress(codec_id: u8, data: &[u8], output: &mut Vec<u8>) -> io::Result<()> {
+    match codec_id {
+        NO_COMPRESSION_ID => NoCompression.decompress(data, output),
+        #[cfg(feature = "lz4")]
+        LZ4_ID => Lz4.decompress(data, output),
+        #[cfg(feature = "zstd")]
+        ZSTD_ID => Zstd::default().decompress(data, output),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                format!("unsupported block codec {}", codec_id))),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress, BlockCodec, NoCompression};
+
+    fn aux_test_codec<C: BlockCodec>(codec: C) {
+        let data: Vec<u8> = (0..10_000u32).flat_map(|i| format!("key{}", i % 300).into_bytes()).collect();
+        let mut compressed = Vec::new();
+        codec.compress(&data[..], &mut compressed).unwrap();
+        let mut decompressed = Vec::new();
+        decompress(codec.id(), &compressed[..], &mut decompressed).unwrap();
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    fn test_no_compression() {
+        aux_test_codec(NoCompression);
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_lz4() {
+        aux_test_codec(super::Lz4);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd() {
+        aux_test_codec(super::Zstd::default());
+    }
+
+    #[test]
+    fn test_unknown_codec() {
+        assert!(decompress(17u8, b"abc", &mut Vec::new()).is_err());
+    }
+}
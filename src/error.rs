@@ -0,0 +1,42 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Error raised when a checksum does not match the data it covers.
+///
+/// It is returned wrapped in an `io::Error` of kind `InvalidData`,
+/// and can be retrieved with `CorruptionError::from_io_error`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CorruptionError {
+    /// Ordinal of the corrupted block, or `None` if the corruption is in the
+    /// sections following the blocks: bloom filter, index, metadata or footer.
+    pub block_id: Option<usize>,
+    /// Offset of the corrupted block in the file, or of the end of the blocks.
+    pub offset: u64,
+}
+
+impl CorruptionError {
+
+    pub fn from_io_error(err: &io::Error) -> Option<&CorruptionError> {
+        err.get_ref().and_then(|err| err.downcast_ref::<CorruptionError>())
+    }
+}
+
+impl fmt::Display for CorruptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.block_id {
+            Some(block_id) =>
+                write!(f, "block {} at offset {} is corrupted (checksum mismatch)", block_id, self.offset),
+            None =>
+                write!(f, "sections after offset {} are corrupted (checksum mismatch)", self.offset),
+        }
+    }
+}
+
+impl Error for CorruptionError {}
+
+impl From<CorruptionError> for io::Error {
+    fn from(err: CorruptionError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
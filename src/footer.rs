@@ -0,0 +1,303 @@
+use std::collections::BTreeMap;
+use std::io;
+use byteorder::{ByteOrder, LittleEndian};
+use crc32c;
+use vint;
+use error::CorruptionError;
+use read_at::ReadAt;
+
+/// Identifies sstable files. Stored in the last 4 bytes of the file.
+pub const MAGIC_NUMBER: u32 = 0x7462_7373; // "sstb"
+
+/// Version of the file format written by this crate.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The footer contains the offsets of the bloom filter, of the index and
+/// of the metadata, the checksums of the metadata and of the sections, the
+/// format version and the magic number.
+pub(crate) const FOOTER_LEN: u64 = 40;
+
+/// Number of bytes of the offsets of the footer.
+const FOOTER_OFFSETS_LEN: u64 = 24;
+
+/// Number of bytes of the footer covered by the checksum of the sections,
+/// i.e. its offsets and the checksum of the metadata.
+const FOOTER_CHECKED_LEN: u64 = 28;
+
+/// Length of the zero-length block header ending the blocks.
+const END_OF_BLOCKS_LEN: u64 = 4;
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Fixed-size trailer of an sstable file.
+///
+/// ```text
+/// blocks | 0u32 | bloom filter | index | metadata
+///     | filter_offset: u64 | index_offset: u64 | metadata_offset: u64
+///     | metadata_checksum: u32 | checksum: u32 | version: u32 | magic: u32
+/// ```
+///
+/// The bloom filter section is empty if the sstable has no filter.
+///
+/// The metadata checksum is the crc32c of the metadata and of the offsets
+/// of the footer, so that the metadata can be read on its own.
+///
+/// The checksum is the crc32c of everything between the blocks and itself:
+/// the zero-length block ending the blocks, the bloom filter, the index,
+/// the metadata, the offsets of the footer and the metadata checksum.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Footer {
+    pub filter_offset: u64,
+    pub index_offset: u64,
+    pub metadata_offset: u64,
+    /// Offset of the footer itself, i.e. the length of the file minus `FOOTER_LEN`.
+    pub footer_offset: u64,
+    pub metadata_checksum: u32,
+    pub checksum: u32,
+}
+
+/// Returns the checksum of the sections following the blocks, given
+/// the bloom filter, index and metadata sections, and the serialized
+/// offsets and metadata checksum of the footer.
+fn sections_checksum(sections: &[u8], footer: &[u8]) -> u32 {
+    let checksum = crc32c::crc32c(&[0u8; END_OF_BLOCKS_LEN as usize]);
+    let checksum = crc32c::crc32c_append(checksum, sections);
+    crc32c::crc32c_append(checksum, footer)
+}
+
+impl Footer {
+
+    /// Appends the footer to `sections`, which holds the bloom filter,
+    /// index and metadata sections, starting at `filter_offset`.
+    ///
+    /// The checksums of the footer are computed from them, and
+    /// `self.metadata_checksum` and `self.checksum` are ignored.
+    pub fn serialize(&self, sections: &mut Vec<u8>) {
+        let mut buf = [0u8; FOOTER_LEN as usize];
+        LittleEndian::write_u64(&mut buf[0..8], self.filter_offset);
+        LittleEndian::write_u64(&mut buf[8..16], self.index_offset);
+        LittleEndian::write_u64(&mut buf[16..24], self.metadata_offset);
+        let metadata = &sections[(self.metadata_offset - self.filter_offset) as usize..];
+        let metadata_checksum = crc32c::crc32c_append(crc32c::crc32c(metadata), &buf[..FOOTER_OFFSETS_LEN as usize]);
+        LittleEndian::write_u32(&mut buf[24..28], metadata_checksum);
+        let checksum = sections_checksum(&sections[..], &buf[..FOOTER_CHECKED_LEN as usize]);
+        LittleEndian::write_u32(&mut buf[28..32], checksum);
+        LittleEndian::write_u32(&mut buf[32..36], FORMAT_VERSION);
+        LittleEndian::write_u32(&mut buf[36..40], MAGIC_NUMBER);
+        sections.extend_from_slice(&buf[..]);
+    }
+
+    pub fn deserialize(data: &[u8], footer_offset: u64) -> io::Result<Footer> {
+        if data.len() != FOOTER_LEN as usize {
+            return Err(invalid_data("sstable is too short to contain a footer"));
+        }
+        if LittleEndian::read_u32(&data[36..40]) != MAGIC_NUMBER {
+            return Err(invalid_data("not an sstable, or truncated sstable (magic number mismatch)"));
+        }
+        let version = LittleEndian::read_u32(&data[32..36]);
+        if version != FORMAT_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("unsupported sstable format version {}", version)));
+        }
+        let filter_offset = LittleEndian::read_u64(&data[0..8]);
+        let index_offset = LittleEndian::read_u64(&data[8..16]);
+        let metadata_offset = LittleEndian::read_u64(&data[16..24]);
+        let metadata_checksum = LittleEndian::read_u32(&data[24..28]);
+        let checksum = LittleEndian::read_u32(&data[28..32]);
+        if filter_offset < END_OF_BLOCKS_LEN || filter_offset > index_offset
+            || index_offset > metadata_offset || metadata_offset > footer_offset {
+            return Err(invalid_data("invalid sstable footer"));
+        }
+        Ok(Footer { filter_offset, index_offset, metadata_offset, footer_offset, metadata_checksum, checksum })
+    }
+
+    /// Reads the bloom filter, index and metadata sections, i.e. the bytes
+    /// `filter_offset..footer_offset`, and checks the checksum of the footer.
+    ///
+    /// A checksum mismatch is reported as a `CorruptionError`.
+    pub fn read_sections<R: ReadAt + ?Sized>(&self, source: &R) -> io::Result<Vec<u8>> {
+        let start = self.filter_offset - END_OF_BLOCKS_LEN;
+        let end = self.footer_offset + FOOTER_CHECKED_LEN;
+        let mut data = vec![0u8; (end - start) as usize];
+        source.read_exact_at(&mut data[..], start)?;
+        if crc32c::crc32c(&data[..]) != self.checksum {
+            return Err(CorruptionError { block_id: None, offset: start }.into());
+        }
+        data.truncate((self.footer_offset - start) as usize);
+        data.drain(..END_OF_BLOCKS_LEN as usize);
+        Ok(data)
+    }
+
+    /// Reads the metadata section only, and checks the checksum of the
+    /// metadata.
+    ///
+    /// A checksum mismatch is reported as a `CorruptionError`.
+    pub fn read_metadata<R: ReadAt + ?Sized>(&self, source: &R) -> io::Result<Vec<u8>> {
+        let start = self.metadata_offset;
+        let end = self.footer_offset + FOOTER_OFFSETS_LEN;
+        let mut data = vec![0u8; (end - start) as usize];
+        source.read_exact_at(&mut data[..], start)?;
+        if crc32c::crc32c(&data[..]) != self.metadata_checksum {
+            return Err(CorruptionError { block_id: None, offset: start }.into());
+        }
+        data.truncate((self.footer_offset - start) as usize);
+        Ok(data)
+    }
+
+    pub fn read<R: ReadAt + ?Sized>(source: &R) -> io::Result<Footer> {
+        let file_len = source.num_bytes()?;
+        if file_len < FOOTER_LEN {
+            return Err(invalid_data("sstable is too short to contain a footer"));
+        }
+        let footer_offset = file_len - FOOTER_LEN;
+        let mut buf = [0u8; FOOTER_LEN as usize];
+        source.read_exact_at(&mut buf[..], footer_offset)?;
+        Footer::deserialize(&buf[..], footer_offset)
+    }
+}
+
+/// Summary of an sstable, stored right before its footer.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SSTableMetadata {
+    pub num_keys: u64,
+    pub num_blocks: u64,
+    /// First key of the sstable, or `None` if it is empty.
+    pub first_key: Option<Vec<u8>>,
+    /// Last key of the sstable, or `None` if it is empty.
+    pub last_key: Option<Vec<u8>>,
+    /// User-supplied properties, set with `Writer::set_property`.
+    pub properties: BTreeMap<String, String>,
+}
+
+impl SSTableMetadata {
+
+    /// Reads the metadata of an sstable, without reading its bloom filter
+    /// or its index.
+    pub fn read<R: ReadAt + ?Sized>(source: &R) -> io::Result<SSTableMetadata> {
+        let footer = Footer::read(source)?;
+        SSTableMetadata::deserialize(&footer.read_metadata(source)?)
+    }
+
+    pub fn serialize(&self, output: &mut Vec<u8>) {
+        vint::serialize_into_vec(self.num_keys, output);
+        vint::serialize_into_vec(self.num_blocks, output);
+        if self.num_keys > 0 {
+            vint::write_bytes(self.first_key.as_ref().map(|key| &key[..]).unwrap_or(b""), output);
+            vint::write_bytes(self.last_key.as_ref().map(|key| &key[..]).unwrap_or(b""), output);
+        }
+        vint::serialize_into_vec(self.properties.len() as u64, output);
+        for (key, value) in &self.properties {
+            vint::write_bytes(key.as_bytes(), output);
+            vint::write_bytes(value.as_bytes(), output);
+        }
+    }
+
+    pub fn deserialize(mut data: &[u8]) -> io::Result<SSTableMetadata> {
+        let num_keys = vint::read_u64(&mut data)?;
+        let num_blocks = vint::read_u64(&mut data)?;
+        let (first_key, last_key) = if num_keys > 0 {
+            let first_key = vint::read_bytes(&mut data)?.to_vec();
+            let last_key = vint::read_bytes(&mut data)?.to_vec();
+            (Some(first_key), Some(last_key))
+        } else {
+            (None, None)
+        };
+        let num_properties = vint::read_u64(&mut data)?;
+        let mut properties = BTreeMap::new();
+        for _ in 0..num_properties {
+            let key = read_string(&mut data)?;
+            let value = read_string(&mut data)?;
+            properties.insert(key, value);
+        }
+        Ok(SSTableMetadata { num_keys, num_blocks, first_key, last_key, properties })
+    }
+}
+
+fn read_string(data: &mut &[u8]) -> io::Result<String> {
+    let bytes = vint::read_bytes(data)?;
+    String::from_utf8(bytes.to_vec())
+        .map_err(|_| invalid_data("sstable property is not valid utf-8"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Footer, SSTableMetadata, FOOTER_LEN};
+    use CorruptionError;
+
+    #[test]
+    fn test_footer_serialization() {
+        let footer = Footer { filter_offset: 6, index_offset: 10, metadata_offset: 30, footer_offset: 40, metadata_checksum: 0, checksum: 0 };
+        let mut buffer = vec![0u8; 34];
+        footer.serialize(&mut buffer);
+        let buffer = buffer.split_off(34);
+        assert_eq!(buffer.len(), FOOTER_LEN as usize);
+        let deserialized = Footer::deserialize(&buffer[..], 40).unwrap();
+        assert_eq!(deserialized, Footer { metadata_checksum: deserialized.metadata_checksum, checksum: deserialized.checksum, ..footer });
+        assert!(Footer::deserialize(&buffer[..], 20).is_err());
+        assert!(Footer::deserialize(&buffer[1..], 40).is_err());
+        let mut wrong_version = buffer.clone();
+        wrong_version[32] = 2;
+        assert!(Footer::deserialize(&wrong_version[..], 40).is_err());
+        let mut wrong_magic = buffer.clone();
+        wrong_magic[39] = 0;
+        assert!(Footer::deserialize(&wrong_magic[..], 40).is_err());
+    }
+
+    #[test]
+    fn test_footer_checksum() {
+        let footer = Footer { filter_offset: 6, index_offset: 10, metadata_offset: 20, footer_offset: 30, metadata_checksum: 0, checksum: 0 };
+        let mut file = vec![1u8, 2u8, 0u8, 0u8, 0u8, 0u8];
+        let mut sections: Vec<u8> = (0..24u8).collect();
+        footer.serialize(&mut sections);
+        file.extend_from_slice(&sections[..]);
+        let footer = Footer::read(&file[..]).unwrap();
+        assert_eq!(footer.read_sections(&file[..]).unwrap(), (0..24u8).collect::<Vec<u8>>());
+        let mut corrupted_file = file.clone();
+        corrupted_file[0] ^= 1u8;
+        assert!(footer.read_sections(&corrupted_file[..]).is_ok());
+        for &corrupted_byte in &[2, 5, 6, 20, 29, 38, 46, 55] {
+            let mut corrupted_file = file.clone();
+            corrupted_file[corrupted_byte] ^= 1u8;
+            let err = Footer::read(&corrupted_file[..])
+                .and_then(|footer| footer.read_sections(&corrupted_file[..]))
+                .unwrap_err();
+            assert_eq!(CorruptionError::from_io_error(&err), Some(&CorruptionError { block_id: None, offset: 2 }));
+        }
+        // The metadata checksum only covers the metadata and the offsets.
+        assert_eq!(footer.read_metadata(&file[..]).unwrap(), (14..24u8).collect::<Vec<u8>>());
+        for &corrupted_byte in &[2, 6, 19] {
+            let mut corrupted_file = file.clone();
+            corrupted_file[corrupted_byte] ^= 1u8;
+            assert!(footer.read_metadata(&corrupted_file[..]).is_ok());
+        }
+        for &corrupted_byte in &[20, 29, 38, 55] {
+            let mut corrupted_file = file.clone();
+            corrupted_file[corrupted_byte] ^= 1u8;
+            let err = Footer::read(&corrupted_file[..])
+                .and_then(|footer| footer.read_metadata(&corrupted_file[..]))
+                .unwrap_err();
+            assert_eq!(CorruptionError::from_io_error(&err), Some(&CorruptionError { block_id: None, offset: 20 }));
+        }
+    }
+
+    #[test]
+    fn test_metadata_serialization() {
+        let mut metadata = SSTableMetadata::default();
+        let mut buffer = Vec::new();
+        metadata.serialize(&mut buffer);
+        assert_eq!(SSTableMetadata::deserialize(&buffer[..]).unwrap(), metadata);
+        metadata.num_keys = 3;
+        metadata.num_blocks = 1;
+        metadata.first_key = Some(b"".to_vec());
+        metadata.last_key = Some(b"abc".to_vec());
+        metadata.properties.insert("created_by".to_string(), "test".to_string());
+        metadata.properties.insert("segment".to_string(), "42".to_string());
+        let mut buffer = Vec::new();
+        metadata.serialize(&mut buffer);
+        assert_eq!(SSTableMetadata::deserialize(&buffer[..]).unwrap(), metadata);
+        assert!(SSTableMetadata::deserialize(&buffer[..buffer.len() - 1]).is_err());
+    }
+}
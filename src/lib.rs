@@ -6,22 +6,77 @@ static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 extern crate slice_deque;
 extern crate core;
 extern crate byteorder;
+extern crate crc32c;
+#[cfg(feature = "lz4")]
+extern crate lz4_flex;
+#[cfg(feature = "zstd")]
+extern crate zstd;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate bincode;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(test)]
+extern crate tempfile;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
 
 use std::io::{self, Write, BufWriter};
+use std::ops::Bound;
+#[cfg(feature = "mmap")]
+use std::path::Path;
+use std::sync::Arc;
 use merge::ValueMerger;
-use byteorder::{ByteOrder, LittleEndian};
-use std::usize;
+use footer::Footer;
+use bloom::BloomFilter;
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+#[cfg(feature = "serde")]
+use std::marker::PhantomData;
 
 pub(crate) mod vint;
+mod bitpacker;
 pub mod value;
 pub mod merge;
+pub mod codec;
 mod block_reader;
+mod sstable_index;
+mod footer;
+mod error;
+mod table;
+mod options;
+mod bloom;
+mod reverse_reader;
+pub mod automaton;
+mod read_at;
+mod block_cache;
+#[cfg(feature = "mmap")]
+mod mmap;
 
 pub use self::block_reader::BlockReader;
-
-pub use self::merge::VoidMerge;
-
-const BLOCK_LEN: usize = 256_000;
+pub use self::sstable_index::{BlockMeta, SSTableIndex};
+pub use self::footer::{SSTableMetadata, FORMAT_VERSION, MAGIC_NUMBER};
+pub use self::table::Table;
+pub use self::reverse_reader::ReverseReader;
+pub use self::error::CorruptionError;
+pub use self::options::{WriterOptions, DEFAULT_BLOCK_SIZE, DEFAULT_RESTART_INTERVAL};
+pub use self::bloom::BloomFilterOptions;
+pub use self::read_at::ReadAt;
+pub use self::block_cache::BlockCache;
+#[cfg(feature = "mmap")]
+pub use self::mmap::MmapTable;
+
+pub use self::merge::{VoidMerge, KeepFirst, SumMerge};
+
+/// Blocks start with their length, encoded as a u32.
+/// The length includes the codec id, but not the checksum.
+const BLOCK_HEADER_LEN: u64 = 4;
+/// Unless written without checksum, blocks end with the crc32c checksum
+/// of their codec id and content.
+const BLOCK_CHECKSUM_LEN: u64 = 4;
+/// Set on the codec id of blocks followed by a checksum.
+const BLOCK_CHECKSUM_FLAG: u8 = 0x80;
 const END_CODE: u8 = 0u8;
 const VINT_MODE: u8 = 1u8;
 
@@ -37,40 +92,92 @@ pub(crate) fn common_prefix_len(left: &[u8], right: &[u8]) -> usize {
 
 pub trait SSTable: Sized {
 
-    type Value;
+    type Value: ToOwned + ?Sized;
     type Reader: value::ValueReader<Value=Self::Value>;
     type Writer: value::ValueWriter<Value=Self::Value>;
 
     fn delta_writer<W: io::Write>(write: W) -> DeltaWriter<W, Self::Writer> {
+        Self::delta_writer_with_options(write, WriterOptions::default())
+    }
+
+    fn delta_writer_with_options<W: io::Write>(write: W, options: WriterOptions) -> DeltaWriter<W, Self::Writer> {
+        assert!(options.restart_interval > 0, "The restart interval should be greater than 0.");
+        assert!(options.restart_interval <= u32::MAX as usize, "The restart interval should fit in a u32.");
         DeltaWriter {
-            block: vec![0u8; 4],
+            block: Vec::with_capacity(options.block_size),
+            num_keys_in_block: 0,
+            restart_offsets: Vec::new(),
+            value_block: Vec::new(),
+            compressed_block: Vec::new(),
+            bloom_filter: options.bloom_filter.map(BloomFilter::new),
+            options,
             write: BufWriter::new(write),
-            value_writer: Self::Writer::default()
+            value_writer: Self::Writer::default(),
+            last_key: Vec::with_capacity(DEFAULT_KEY_CAPACITY),
+            num_bytes_written: 0u64,
+            index: SSTableIndex::default(),
+            metadata: SSTableMetadata::default(),
         }
     }
 
     fn writer<W: io::Write>(write: W) -> Writer<W, Self::Writer> {
+        Self::writer_with_options(write, WriterOptions::default())
+    }
+
+    fn writer_with_options<W: io::Write>(write: W, options: WriterOptions) -> Writer<W, Self::Writer> {
         Writer {
             previous_key: Vec::with_capacity(DEFAULT_KEY_CAPACITY),
-            delta_writer: Self::delta_writer(write)
+            delta_writer: Self::delta_writer_with_options(write, options)
         }
     }
 
     fn delta_reader<'a, R: io::Read + 'a>(reader: R) -> DeltaReader<'a, Self::Reader> {
+        Self::delta_reader_from_block_reader(BlockReader::new(Box::new(reader)))
+    }
+
+    fn delta_reader_from_block_reader<'a>(block_reader: BlockReader<'a>) -> DeltaReader<'a, Self::Reader> {
         DeltaReader {
             common_prefix_len: 0,
             suffix_start: 0,
             suffix_end: 0,
+            value_start: 0,
             offset: 0,
+            block_end: 0,
+            restart_interval: 1,
+            num_restarts: 0,
+            entry_idx: 0,
             value_reader: Self::Reader::default(),
-            block_reader: BlockReader::new(Box::new(reader)),
+            block_reader,
         }
     }
 
     fn reader<'a, R: io::Read + 'a>(reader: R) -> Reader<'a, Self::Reader> {
+        Self::reader_from_block_reader(BlockReader::new(Box::new(reader)))
+    }
+
+    /// Returns a reader over an sstable held in memory.
+    ///
+    /// Uncompressed blocks are read in place, without being copied.
+    fn reader_from_bytes<'a>(data: &'a [u8]) -> Reader<'a, Self::Reader> {
+        Self::reader_from_block_reader(BlockReader::from_bytes(data))
+    }
+
+    /// Same as `reader_from_bytes`, for an sstable in a shared buffer.
+    fn reader_from_shared_bytes(data: Arc<[u8]>) -> Reader<'static, Self::Reader> {
+        Self::reader_from_block_reader(BlockReader::from_shared_bytes(data))
+    }
+
+    fn reader_from_block_reader<'a>(block_reader: BlockReader<'a>) -> Reader<'a, Self::Reader> {
         Reader {
             key: Vec::with_capacity(DEFAULT_KEY_CAPACITY),
-            delta_reader: Self::delta_reader(reader)
+            lower_bound: Bound::Unbounded,
+            upper_bound: Bound::Unbounded,
+            prefix: None,
+            index: None,
+            next_ord: 0,
+            has_key: false,
+            finished: false,
+            delta_reader: Self::delta_reader_from_block_reader(block_reader)
         }
     }
 
@@ -83,6 +190,54 @@ pub trait SSTable: Sized {
         let writer = Self::writer(w);
         merge::merge_sstable::<Self, _, _>(readers, writer, merger)
     }
+
+    fn table<R: ReadAt>(source: R) -> io::Result<Table<Self, R>> {
+        Table::open(source)
+    }
+
+    /// Memory maps the sstable file at `path`.
+    ///
+    /// The returned handle can be cloned and shared between threads.
+    #[cfg(feature = "mmap")]
+    fn open<P: AsRef<Path>>(path: P) -> io::Result<MmapTable<Self>> {
+        MmapTable::open(path)
+    }
+
+    /// Checks the checksums of all of the blocks of an sstable, and of the
+    /// sections following them.
+    ///
+    /// Corruptions are reported as a `CorruptionError`.
+    fn verify<R: ReadAt>(source: R) -> io::Result<()> {
+        Self::table(source)?.verify()
+    }
+
+    /// Looks up a single key.
+    ///
+    /// This loads the block index of the sstable and decodes only one block.
+    /// Use `SSTable::table` to run several lookups against the same sstable.
+    fn get<R: ReadAt>(source: R, key: &[u8]) -> io::Result<Option<<Self::Value as ToOwned>::Owned>> {
+        Self::table(source)?.get(key)
+    }
+
+    /// Returns a reader over the keys within `lower` and `upper`.
+    ///
+    /// The block index is used to start reading at the block containing
+    /// the first key of the range.
+    fn range<'a, R: ReadAt + 'a>(source: R, lower: Bound<&[u8]>, upper: Bound<&[u8]>)
+        -> io::Result<Reader<'a, Self::Reader>> {
+        Self::table(source)?.into_range(lower, upper)
+    }
+
+    /// Returns a reader over the keys starting with `prefix`.
+    fn prefix<'a, R: ReadAt + 'a>(source: R, prefix: &[u8]) -> io::Result<Reader<'a, Self::Reader>> {
+        Self::table(source)?.into_prefix(prefix)
+    }
+
+    /// Returns a reader over the keys accepted by `automaton`.
+    fn search<'a, R: ReadAt + 'a, A: automaton::Automaton>(source: R, automaton: A)
+        -> io::Result<automaton::SearchReader<'a, Self::Reader, A>> {
+        Self::table(source)?.into_search(automaton)
+    }
 }
 
 pub struct VoidSSTable;
@@ -93,27 +248,256 @@ impl SSTable for VoidSSTable {
     type Writer = value::VoidWriter;
 }
 
+/// SSTable associating a vint-encoded `u64` to each key.
+pub struct U64SSTable;
+
+impl SSTable for U64SSTable {
+    type Value = u64;
+    type Reader = value::U64Reader;
+    type Writer = value::U64Writer;
+}
+
+/// SSTable associating a `u64` to each key.
+///
+/// Unlike `U64SSTable`, the values of a block are bit-packed
+/// together at the beginning of the block.
+pub struct BitpackedU64SSTable;
+
+impl SSTable for BitpackedU64SSTable {
+    type Value = u64;
+    type Reader = value::BitpackedU64Reader;
+    type Writer = value::BitpackedU64Writer;
+}
+
+/// SSTable associating a `TermInfo` to each key.
+///
+/// Term info offsets are delta-encoded within each block, and therefore
+/// need to be increasing.
+pub struct TermInfoSSTable;
+
+impl SSTable for TermInfoSSTable {
+    type Value = value::TermInfo;
+    type Reader = value::TermInfoReader;
+    type Writer = value::TermInfoWriter;
+}
+
+/// SSTable associating a length-prefixed byte slice to each key.
+///
+/// Values are read without being copied out of the block.
+pub struct BytesSSTable;
+
+impl SSTable for BytesSSTable {
+    type Value = [u8];
+    type Reader = value::BytesReader;
+    type Writer = value::BytesWriter;
+}
+
+
+/// SSTable associating a serde-serializable value to each key.
+///
+/// Values are encoded with bincode, using varint encoding for integers.
+///
+/// # Panics
+///
+/// Writing a value that bincode fails to serialize, such as a sequence
+/// whose length is not known in advance, panics.
+///
+/// As with the other sstables, `Reader::value` returns the default value
+/// before the first call to `Reader::advance`.
+#[cfg(feature = "serde")]
+pub struct SerdeSSTable<T>(PhantomData<T>);
+
+#[cfg(feature = "serde")]
+impl<T> SSTable for SerdeSSTable<T>
+    where T: serde::Serialize + serde::de::DeserializeOwned + Clone + Default {
+    type Value = T;
+    type Reader = value::SerdeReader<T>;
+    type Writer = value::SerdeWriter<T>;
+}
+
+pub(crate) fn to_owned_bound(bound: Bound<&[u8]>) -> Bound<Vec<u8>> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key.to_vec()),
+        Bound::Excluded(key) => Bound::Excluded(key.to_vec()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+pub(crate) fn above_lower_bound(key: &[u8], lower: &Bound<Vec<u8>>) -> bool {
+    match *lower {
+        Bound::Included(ref lower) => key >= &lower[..],
+        Bound::Excluded(ref lower) => key > &lower[..],
+        Bound::Unbounded => true,
+    }
+}
+
+pub(crate) fn below_upper_bound(key: &[u8], upper: &Bound<Vec<u8>>) -> bool {
+    match *upper {
+        Bound::Included(ref upper) => key <= &upper[..],
+        Bound::Excluded(ref upper) => key < &upper[..],
+        Bound::Unbounded => true,
+    }
+}
 
 pub struct Reader<'a, TValueReader> {
     key: Vec<u8>,
+    /// Keys below the lower bound are skipped. It is reset to
+    /// `Unbounded` once the first key of the range has been reached.
+    lower_bound: Bound<Vec<u8>>,
+    upper_bound: Bound<Vec<u8>>,
+    /// If set, the reader stops at the first key not starting with this prefix.
+    prefix: Option<Vec<u8>>,
+    /// Index of the sstable, if known, used to skip blocks in `seek`.
+    index: Option<Arc<SSTableIndex>>,
+    /// Ordinal of the next key to be decoded.
+    next_ord: u64,
+    /// `true` if the reader is positioned on a key.
+    has_key: bool,
+    finished: bool,
     delta_reader: DeltaReader<'a, TValueReader>,
 }
 
 impl<'a, TValueReader> Reader<'a, TValueReader>
     where TValueReader: value::ValueReader {
 
-    pub fn advance(&mut self) -> io::Result<bool> {
+    /// Restricts the keys returned by the reader to the given range.
+    pub(crate) fn set_bounds(&mut self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) {
+        self.lower_bound = to_owned_bound(lower);
+        self.upper_bound = to_owned_bound(upper);
+    }
+
+    /// Restricts the keys returned by the reader to those starting with `prefix`.
+    ///
+    /// The reader should not return any key before the first key with the
+    /// prefix, e.g. because its lower bound is the prefix itself.
+    pub(crate) fn set_prefix(&mut self, prefix: &[u8]) {
+        self.prefix = Some(prefix.to_vec());
+    }
+
+    /// Declares the ordinal of the next key to be decoded, for readers
+    /// that do not start at the beginning of the sstable.
+    pub(crate) fn set_next_ord(&mut self, next_ord: u64) {
+        self.next_ord = next_ord;
+    }
+
+    /// Gives the reader access to the index of the sstable, for it to skip
+    /// blocks in `seek`.
+    ///
+    /// The block reader positions must have been set.
+    pub(crate) fn set_index(&mut self, index: Arc<SSTableIndex>) {
+        self.index = Some(index);
+    }
+
+    /// Makes the reader behave as if it had reached its last key.
+    pub(crate) fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    fn advance_key(&mut self) -> io::Result<bool> {
         if self.delta_reader.advance()? {
             let common_prefix_len = self.delta_reader.common_prefix_len();
             let suffix = self.delta_reader.suffix();
             let new_len = self.delta_reader.common_prefix_len() + suffix.len();
             self.key.resize(new_len, 0u8);
             self.key[common_prefix_len..].copy_from_slice(suffix);
+            self.next_ord += 1;
             Ok(true)
         } else {
             Ok(false)
         }
+    }
+
+    /// Returns the ordinal of the current key, i.e. its 0-based position
+    /// in the sstable.
+    ///
+    /// Only valid while the reader is positioned on a key.
+    pub fn ord(&self) -> u64 {
+        debug_assert!(self.has_key);
+        self.next_ord - 1
+    }
+
+    pub fn advance(&mut self) -> io::Result<bool> {
+        loop {
+            if self.finished || !self.advance_key()? {
+                self.finished = true;
+                self.has_key = false;
+                return Ok(false);
+            }
+            if !above_lower_bound(&self.key[..], &self.lower_bound) {
+                continue;
+            }
+            self.lower_bound = Bound::Unbounded;
+            let in_range = below_upper_bound(&self.key[..], &self.upper_bound) && match self.prefix {
+                // Keys before the prefix are skipped, so a key sharing at least
+                // `prefix.len()` bytes with the previous key has the prefix.
+                // The first key of a block shares no byte with the previous
+                // key, and requires a full comparison.
+                Some(ref prefix) =>
+                    self.delta_reader.common_prefix_len() >= prefix.len()
+                        || self.key.starts_with(prefix),
+                None => true,
+            };
+            if !in_range {
+                self.finished = true;
+            }
+            self.has_key = in_range;
+            return Ok(in_range);
+        }
+    }
 
+    /// Skips the entries of the current block before the key of ordinal `ord`,
+    /// up to the last restart point before it.
+    ///
+    /// `ord` is expected to belong to the current block, or to the next one
+    /// if the current block has been entirely read.
+    pub(crate) fn skip_to_restart_before_ord(&mut self, ord: u64) -> io::Result<()> {
+        self.delta_reader.ensure_block()?;
+        let entry_idx = self.delta_reader.entry_idx() + ord.saturating_sub(self.next_ord) as usize;
+        self.next_ord += self.delta_reader.skip_to_restart_before_entry(entry_idx)? as u64;
+        Ok(())
+    }
+
+    /// Moves the reader to the first key greater or equal to `target`.
+    ///
+    /// The reader does not move if it is already positioned on such a key.
+    /// Returns `false` if there is no such key.
+    ///
+    /// If the reader was created from a `Table`, blocks that cannot contain
+    /// `target` are skipped without being decoded. Within a block, decoding
+    /// starts from the last restart point before `target`.
+    pub fn seek(&mut self, target: &[u8]) -> io::Result<bool> {
+        if self.has_key && &self.key[..] >= target {
+            return Ok(true);
+        }
+        if self.finished {
+            return Ok(false);
+        }
+        if let Some(index) = self.index.clone() {
+            let next_block_id = self.delta_reader.block_reader.next_block_id();
+            match index.search(target) {
+                Some(block_id) if block_id >= next_block_id => {
+                    // `target` is past the current block.
+                    let block_meta = &index.blocks()[block_id];
+                    self.delta_reader.skip_to_block(block_id, block_meta.offset)?;
+                    self.next_ord = block_meta.first_ord;
+                }
+                Some(_) => {}
+                None => {
+                    self.finished = true;
+                    self.has_key = false;
+                    return Ok(false);
+                }
+            }
+        }
+        // Within the block, the restart points are binary searched.
+        self.delta_reader.ensure_block()?;
+        self.next_ord += self.delta_reader.skip_to_restart_before(target)? as u64;
+        while self.advance()? {
+            if &self.key[..] >= target {
+                return Ok(true);
+            }
+        }
+        Ok(false)
     }
 
     pub fn key(&self) -> &[u8] {
@@ -165,6 +549,11 @@ impl<W, TValueWriter> Writer<W, TValueWriter>
             &key[keep_len..]);
     }
 
+    /// Sets a user-defined property, stored in the metadata of the sstable.
+    pub fn set_property(&mut self, key: &str, value: &str) {
+        self.delta_writer.set_property(key, value);
+    }
+
     pub(crate) fn into_delta_writer(self) -> DeltaWriter<W, TValueWriter> {
         self.delta_writer
     }
@@ -189,38 +578,120 @@ impl<W, TValueWriter> Writer<W, TValueWriter>
 pub struct DeltaWriter<W, TValueWriter>
     where W: io::Write {
     block: Vec<u8>,
+    num_keys_in_block: usize,
+    /// Offsets of the restart points within `block`.
+    restart_offsets: Vec<usize>,
+    value_block: Vec<u8>,
+    compressed_block: Vec<u8>,
+    options: WriterOptions,
+    bloom_filter: Option<BloomFilter>,
     write: BufWriter<W>,
     value_writer: TValueWriter,
+    last_key: Vec<u8>,
+    num_bytes_written: u64,
+    index: SSTableIndex,
+    metadata: SSTableMetadata,
 }
 
 impl<W, TValueWriter> DeltaWriter<W, TValueWriter>
     where W: io::Write, TValueWriter: value::ValueWriter {
 
     fn flush_block(&mut self) -> io::Result<()> {
-        let block_len = self.block.len() as u32;
-        LittleEndian::write_u32(&mut self.block[..4], block_len - 4u32);
-        self.write.write_all(&mut self.block[..])?;
-        self.block.resize(4, 0u8);
+        self.value_block.clear();
+        self.value_writer.serialize_block(&mut self.value_block);
+        let value_section_len = self.value_block.len();
+        self.value_block.extend_from_slice(&self.block[..]);
+        for &restart_offset in &self.restart_offsets {
+            self.value_block.write_u32::<LittleEndian>((value_section_len + restart_offset) as u32)?;
+        }
+        self.value_block.write_u32::<LittleEndian>(self.options.restart_interval as u32)?;
+        self.value_block.write_u32::<LittleEndian>(self.restart_offsets.len() as u32)?;
+        // Restart offsets point within the block, so they fit in a u32 if
+        // the block does.
+        if self.value_block.len() > u32::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "block too large"));
+        }
+        self.compressed_block.clear();
+        let codec = &self.options.codec;
+        if self.options.checksum {
+            self.compressed_block.push(codec.id() | BLOCK_CHECKSUM_FLAG);
+        } else {
+            self.compressed_block.push(codec.id());
+        }
+        codec.compress(&self.value_block[..], &mut self.compressed_block)?;
+        if self.compressed_block.len() > u32::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "block too large"));
+        }
+        let block_len = self.compressed_block.len() as u32;
+        self.write.write_u32::<LittleEndian>(block_len)?;
+        self.write.write_all(&self.compressed_block[..])?;
+        let mut num_bytes = BLOCK_HEADER_LEN + u64::from(block_len);
+        if self.options.checksum {
+            let checksum = crc32c::crc32c(&self.compressed_block[..]);
+            self.write.write_u32::<LittleEndian>(checksum)?;
+            num_bytes += BLOCK_CHECKSUM_LEN;
+        }
+        self.index.push(BlockMeta {
+            last_key: self.last_key.clone(),
+            offset: self.num_bytes_written,
+            num_bytes,
+            first_ord: self.metadata.num_keys - self.num_keys_in_block as u64,
+        });
+        self.num_bytes_written += num_bytes;
+        self.block.clear();
+        self.restart_offsets.clear();
+        self.num_keys_in_block = 0;
         Ok(())
     }
 
     fn encode_keep_add(&mut self, keep_len: usize, add_len: usize) {
-        if keep_len < FOUR_BIT_LIMITS && add_len < FOUR_BIT_LIMITS {
+        // `add_len > 0` ensures the single byte encoding never collides
+        // with `END_CODE` or `VINT_MODE`. (The empty key is the only key with `add_len == 0`.)
+        if keep_len < FOUR_BIT_LIMITS && add_len < FOUR_BIT_LIMITS && add_len > 0 {
             let b = (keep_len | add_len << 4) as u8;
             self.block.extend_from_slice(&[b])
         } else {
             let mut buf = [1u8; 20];
             let mut len = 1 + vint::serialize(keep_len as u64, &mut buf[1..]);
             len += vint::serialize(add_len as u64, &mut buf[len..]);
-            self.block.extend_from_slice(&mut buf[..len])
+            self.block.extend_from_slice(&buf[..len])
         }
     }
 
+    pub(crate) fn last_key(&self) -> &[u8] {
+        &self.last_key[..]
+    }
+
+    /// Sets a user-defined property, stored in the metadata of the sstable.
+    pub fn set_property(&mut self, key: &str, value: &str) {
+        self.metadata.properties.insert(key.to_string(), value.to_string());
+    }
+
     pub(crate) fn write_suffix(&mut self, common_prefix_len: usize, suffix: &[u8]) {
-        let keep_len = common_prefix_len;
-        let add_len = suffix.len();
-        self.encode_keep_add(keep_len, add_len);
-        self.block.extend_from_slice(suffix);
+        self.last_key.truncate(common_prefix_len);
+        self.last_key.extend_from_slice(suffix);
+        if self.metadata.num_keys == 0 {
+            self.metadata.first_key = Some(self.last_key.clone());
+        }
+        self.metadata.num_keys += 1;
+        if let Some(bloom_filter) = self.bloom_filter.as_mut() {
+            bloom_filter.insert(&self.last_key[..]);
+        }
+        let is_restart = self.num_keys_in_block.is_multiple_of(self.options.restart_interval);
+        self.num_keys_in_block += 1;
+        if is_restart {
+            // Restart keys are written in full, so that readers can start
+            // decoding from them. The first key of a block is always a restart,
+            // so that every block can be decoded independently.
+            self.restart_offsets.push(self.block.len());
+            self.value_writer.restart();
+            let key_len = self.last_key.len();
+            self.encode_keep_add(0, key_len);
+            self.block.extend_from_slice(&self.last_key[..]);
+        } else {
+            self.encode_keep_add(common_prefix_len, suffix.len());
+            self.block.extend_from_slice(suffix);
+        }
     }
 
     pub(crate) fn write_value(&mut self, value: &TValueWriter::Value) {
@@ -234,17 +705,46 @@ impl<W, TValueWriter> DeltaWriter<W, TValueWriter>
     }
 
     pub fn flush_block_if_required(&mut self) -> io::Result<()> {
-        if self.block.len() > BLOCK_LEN {
+        let max_keys_reached = self.options.max_keys_per_block
+            .map(|max_keys| self.num_keys_in_block >= max_keys)
+            .unwrap_or(false);
+        if self.block.len() > self.options.block_size || max_keys_reached {
             self.flush_block()?;
         }
         Ok(())
     }
 
     pub fn finalize(mut self) -> io::Result<()> {
-        if self.block.len() > 4 {
+        if self.num_keys_in_block > 0 {
             self.flush_block()?;
         }
-        self.flush_block()?;
+        // A zero-length block marks the end of the blocks.
+        self.write.write_u32::<LittleEndian>(0u32)?;
+        self.num_bytes_written += BLOCK_HEADER_LEN;
+        let filter_offset = self.num_bytes_written;
+        let mut data = Vec::new();
+        if let Some(bloom_filter) = self.bloom_filter.as_ref() {
+            bloom_filter.serialize(&mut data);
+        }
+        let index_offset = filter_offset + data.len() as u64;
+        self.index.serialize(&mut data);
+        let metadata_offset = filter_offset + data.len() as u64;
+        self.metadata.num_blocks = self.index.blocks().len() as u64;
+        if self.metadata.num_keys > 0 {
+            self.metadata.last_key = Some(self.last_key.clone());
+        }
+        self.metadata.serialize(&mut data);
+        let footer = Footer {
+            filter_offset,
+            index_offset,
+            metadata_offset,
+            footer_offset: filter_offset + data.len() as u64,
+            metadata_checksum: 0,
+            checksum: 0,
+        };
+        footer.serialize(&mut data);
+        self.write.write_all(&data[..])?;
+        self.write.flush()?;
         Ok(())
     }
 }
@@ -254,73 +754,199 @@ pub struct DeltaReader<'a, TValueReader> {
     common_prefix_len: usize,
     suffix_start: usize,
     suffix_end: usize,
+    value_start: usize,
     offset: usize,
+    /// End of the entries of the current block, where its restart points start.
+    block_end: usize,
+    restart_interval: usize,
+    num_restarts: usize,
+    /// Number of entries of the current block decoded so far.
+    entry_idx: usize,
     value_reader: TValueReader,
     block_reader: BlockReader<'a>,
 }
 
-impl<'a, TValueReader> DeltaReader<'a, TValueReader>
-    where TValueReader: value::ValueReader {
-
-    fn deserialize_vint(&mut self) -> u64 {
-        let (consumed, result) =
-            vint::deserialize_read(&self.block_reader.buffer()[self.offset..]);
-        self.offset += consumed;
-        result
-    }
-
-    fn read_keep_add(&mut self) -> Option<(usize, usize)> {
-        let b = {
-            let buf = &self.block_reader.buffer()[self.offset..];
-            if buf.is_empty() {
-                return None;
-            }
-            buf[0]
-        };
-        self.offset += 1;
-        match b {
-            END_CODE => {
-                None
-            }
-            VINT_MODE => {
-                let keep = self.deserialize_vint() as usize;
-                let add = self.deserialize_vint() as usize;
-                Some((keep, add))
-            }
-            b => {
-                let keep = (b & 0b1111) as usize;
-                let add = (b >> 4) as usize;
-                Some((keep, add))
-            }
+/// Decodes the lengths of the prefix shared with the previous key and of
+/// the suffix, followed by the number of bytes they took.
+fn read_keep_add(data: &[u8]) -> Option<(usize, usize, usize)> {
+    match *data.first()? {
+        END_CODE => None,
+        VINT_MODE => {
+            let (keep_num_bytes, keep) = vint::deserialize_read(&data[1..]);
+            let (add_num_bytes, add) = vint::deserialize_read(&data[1 + keep_num_bytes..]);
+            Some((keep as usize, add as usize, 1 + keep_num_bytes + add_num_bytes))
+        }
+        b => {
+            let keep = (b & 0b1111) as usize;
+            let add = (b >> 4) as usize;
+            Some((keep, add, 1))
         }
     }
+}
+
+impl<'a, TValueReader> DeltaReader<'a, TValueReader>
+    where TValueReader: value::ValueReader {
 
     fn read_delta_key(&mut self) -> bool {
-        if let Some((keep, add)) = self.read_keep_add() {
+        if let Some((keep, add, num_bytes)) = read_keep_add(&self.block_reader.buffer()[self.offset..self.block_end]) {
             self.common_prefix_len = keep;
-            self.suffix_start = self.offset;
+            self.suffix_start = self.offset + num_bytes;
             self.suffix_end = self.suffix_start + add;
-            self.offset += add;
+            self.offset = self.suffix_end;
             true
         } else {
             false
         }
     }
 
+    /// Loads the next block, and reads its restart points.
+    ///
+    /// Returns `false` if there are no more blocks.
+    fn load_block(&mut self) -> io::Result<bool> {
+        self.offset = 0;
+        self.block_end = 0;
+        self.num_restarts = 0;
+        self.entry_idx = 0;
+        if !self.block_reader.read_block()? {
+            return Ok(false);
+        }
+        let buffer = self.block_reader.buffer();
+        let invalid_trailer = || io::Error::new(io::ErrorKind::InvalidData, "invalid block restart points");
+        if buffer.len() < 8 {
+            return Err(invalid_trailer());
+        }
+        let num_restarts = LittleEndian::read_u32(&buffer[buffer.len() - 4..]) as usize;
+        let restart_interval = LittleEndian::read_u32(&buffer[buffer.len() - 8..]) as usize;
+        let trailer_len = num_restarts.checked_mul(4)
+            .and_then(|restarts_len| restarts_len.checked_add(8))
+            .filter(|&trailer_len| trailer_len <= buffer.len())
+            .ok_or_else(invalid_trailer)?;
+        if restart_interval == 0 {
+            return Err(invalid_trailer());
+        }
+        self.block_end = buffer.len() - trailer_len;
+        self.restart_interval = restart_interval;
+        self.num_restarts = num_restarts;
+        self.offset = self.value_reader.load_block(&buffer[..self.block_end])?;
+        if self.offset > self.block_end {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "value section exceeds the block"));
+        }
+        Ok(true)
+    }
+
+    /// Makes sure a block with entries left to decode is loaded, unless
+    /// there are no more blocks.
+    pub(crate) fn ensure_block(&mut self) -> io::Result<()> {
+        if self.offset == self.block_end {
+            self.load_block()?;
+        }
+        Ok(())
+    }
 
     pub fn advance(&mut self) -> io::Result<bool> {
-        if self.block_reader.buffer().is_empty() {
-            if !self.block_reader.read_block()? {
-                return Ok(false);
-            }
+        if self.offset == self.block_end && !self.load_block()? {
+            return Ok(false);
+        }
+        if self.entry_idx.is_multiple_of(self.restart_interval) {
+            self.value_reader.restart(self.entry_idx);
         }
         if !self.read_delta_key() {
             return Ok(false);
         }
-        self.value_reader.read(&mut self.block_reader)?;
+        if self.offset > self.block_end {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "key exceeds the block"));
+        }
+        self.value_start = self.offset;
+        let num_bytes = self.value_reader.read(&self.block_reader.buffer()[self.offset..self.block_end])?;
+        self.offset += num_bytes;
+        if self.offset > self.block_end {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "value exceeds the block"));
+        }
+        self.entry_idx += 1;
         Ok(true)
     }
 
+    /// Number of entries of the current block decoded so far.
+    pub(crate) fn entry_idx(&self) -> usize {
+        self.entry_idx
+    }
+
+    fn restart_offset(&self, restart: usize) -> io::Result<usize> {
+        let buffer = self.block_reader.buffer();
+        let pos = self.block_end + 4 * restart;
+        let restart_offset = LittleEndian::read_u32(&buffer[pos..pos + 4]) as usize;
+        if restart_offset >= self.block_end {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "restart point exceeds the block"));
+        }
+        Ok(restart_offset)
+    }
+
+    /// Returns the key of a restart point, which is written in full.
+    fn restart_key(&self, restart: usize) -> io::Result<&[u8]> {
+        let restart_offset = self.restart_offset(restart)?;
+        let data = &self.block_reader.buffer()[restart_offset..self.block_end];
+        match read_keep_add(data) {
+            Some((0, add, num_bytes)) if num_bytes + add <= data.len() => Ok(&data[num_bytes..num_bytes + add]),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "invalid restart point")),
+        }
+    }
+
+    /// Jumps to the restart point `restart` of the current block, if it is
+    /// ahead of the next entry. Returns the number of entries skipped.
+    fn jump_to_restart(&mut self, restart: usize) -> io::Result<usize> {
+        let restart_entry_idx = restart * self.restart_interval;
+        if restart_entry_idx <= self.entry_idx {
+            return Ok(0);
+        }
+        self.offset = self.restart_offset(restart)?;
+        let num_skipped = restart_entry_idx - self.entry_idx;
+        self.entry_idx = restart_entry_idx;
+        Ok(num_skipped)
+    }
+
+    /// Skips the entries of the current block that are before `target`,
+    /// up to the last restart point with a key lower than `target`.
+    ///
+    /// Restart points are binary searched. Returns the number of entries skipped.
+    pub(crate) fn skip_to_restart_before(&mut self, target: &[u8]) -> io::Result<usize> {
+        // Number of restart points with a key lower than `target`.
+        let mut lo = 0;
+        let mut hi = self.num_restarts;
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.restart_key(mid)? < target {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        if lo == 0 {
+            return Ok(0);
+        }
+        self.jump_to_restart(lo - 1)
+    }
+
+    /// Skips the entries of the current block, up to the last restart point
+    /// before the entry `entry_idx`. Returns the number of entries skipped.
+    pub(crate) fn skip_to_restart_before_entry(&mut self, entry_idx: usize) -> io::Result<usize> {
+        let restart = entry_idx / self.restart_interval;
+        if restart >= self.num_restarts {
+            return Ok(0);
+        }
+        self.jump_to_restart(restart)
+    }
+
+    /// Skips the rest of the current block, and the following blocks until
+    /// the block `block_id`, starting at `block_offset`.
+    pub(crate) fn skip_to_block(&mut self, block_id: usize, block_offset: u64) -> io::Result<()> {
+        let num_bytes = block_offset - self.block_reader.next_block_offset();
+        self.block_reader.skip(num_bytes, block_id)?;
+        self.offset = 0;
+        self.block_end = 0;
+        self.num_restarts = 0;
+        Ok(())
+    }
+
     pub fn common_prefix_len(&self) -> usize {
         self.common_prefix_len
     }
@@ -334,7 +960,7 @@ impl<'a, TValueReader> DeltaReader<'a, TValueReader>
     }
 
     pub fn value(&self) -> &TValueReader::Value {
-        self.value_reader.value()
+        self.value_reader.value(&self.block_reader.buffer()[self.value_start..self.block_end])
     }
 }
 
@@ -342,9 +968,54 @@ impl<'a, TValueReader> DeltaReader<'a, TValueReader>
 #[cfg(test)]
 mod test {
     use common_prefix_len;
-    use super::VoidSSTable;
+    use super::{VoidSSTable, U64SSTable, BytesSSTable, TermInfoSSTable, BitpackedU64SSTable};
+    use value::TermInfo;
+    use codec;
+    use std::sync::Arc;
+    use WriterOptions;
     use super::SSTable;
-    use VoidMerge;
+    use {VoidMerge, SumMerge};
+    use std::io::{self, Cursor};
+    use byteorder::{ByteOrder, LittleEndian};
+    use value::{ValueReader, ValueWriter};
+    use KeepFirst;
+
+    #[derive(Default)]
+    struct U32Reader(u32);
+
+    impl ValueReader for U32Reader {
+        type Value = u32;
+
+        fn value<'b>(&'b self, _data: &'b [u8]) -> &'b u32 {
+            &self.0
+        }
+
+        fn read(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0 = LittleEndian::read_u32(data);
+            Ok(4)
+        }
+    }
+
+    #[derive(Default)]
+    struct U32Writer;
+
+    impl ValueWriter for U32Writer {
+        type Value = u32;
+
+        fn write(&mut self, val: &u32, writer: &mut Vec<u8>) {
+            let mut buf = [0u8; 4];
+            LittleEndian::write_u32(&mut buf, *val);
+            writer.extend_from_slice(&buf);
+        }
+    }
+
+    struct U32SSTable;
+
+    impl SSTable for U32SSTable {
+        type Value = u32;
+        type Reader = U32Reader;
+        type Writer = U32Writer;
+    }
 
     fn aux_test_common_prefix_len(left: &str, right: &str, expect_len: usize) {
         assert_eq!(common_prefix_len(left.as_bytes(), right.as_bytes()), expect_len);
@@ -392,12 +1063,36 @@ mod test {
             assert!(sstable_writer.write(&[17u8, 20u8], &()).is_ok());
             assert!(sstable_writer.finalize().is_ok());
         }
-        assert_eq!(&buffer, &[
-            7,0,0,0,
+        let block = [
+            0x80u8, // codec id, with the checksum flag
             16u8, 17u8,
             33u8, 18u8, 19u8,
             17u8, 20u8,
-            0u8, 0u8, 0u8, 0u8]);
+            // restart points
+            0u8, 0u8, 0u8, 0u8,
+            16u8, 0u8, 0u8, 0u8,
+            1u8, 0u8, 0u8, 0u8];
+        let mut checksum = [0u8; 4];
+        LittleEndian::write_u32(&mut checksum, crc32c::crc32c(&block));
+        assert_eq!(&buffer[..4], &[20u8, 0u8, 0u8, 0u8]);
+        assert_eq!(&buffer[4..24], &block);
+        assert_eq!(&buffer[24..28], &checksum);
+        let offsets_end = buffer.len() - 16;
+        assert_eq!(&buffer[28..offsets_end], &[
+            0u8, 0u8, 0u8, 0u8,
+            // index
+            1u8, 2u8, 17u8, 20u8, 0u8, 28u8, 0u8,
+            // metadata
+            3u8, 1u8, 1u8, 17u8, 2u8, 17u8, 20u8, 0u8,
+            // footer
+            32u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8,
+            32u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8,
+            39u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8][..]);
+        LittleEndian::write_u32(&mut checksum, crc32c::crc32c(&buffer[39..offsets_end]));
+        assert_eq!(&buffer[offsets_end..offsets_end + 4], &checksum);
+        LittleEndian::write_u32(&mut checksum, crc32c::crc32c(&buffer[28..offsets_end + 4]));
+        assert_eq!(&buffer[offsets_end + 4..offsets_end + 8], &checksum);
+        assert_eq!(&buffer[offsets_end + 8..], &[1u8, 0u8, 0u8, 0u8, b's', b's', b'b', b't']);
         let mut sstable_reader = VoidSSTable::reader(&buffer[..]);
         assert!(sstable_reader.advance().unwrap());
         assert_eq!(sstable_reader.key(), &[17u8]);
@@ -409,6 +1104,442 @@ mod test {
     }
 
 
+    #[test]
+    fn test_blocks_are_self_contained() {
+        let mut buffer = vec![];
+        {
+            let mut sstable_writer = VoidSSTable::writer(&mut buffer);
+            for i in 0..500_000 {
+                let key = format!("{:08}", i);
+                assert!(sstable_writer.write(key.as_bytes(), &()).is_ok());
+            }
+            assert!(sstable_writer.finalize().is_ok());
+        }
+        let table = VoidSSTable::table(Cursor::new(&buffer[..])).unwrap();
+        let blocks = table.index().blocks();
+        assert!(blocks.len() > 1);
+        let mut num_keys = 0;
+        for block_meta in blocks {
+            let start = block_meta.offset as usize;
+            let stop = start + block_meta.num_bytes as usize;
+            let mut sstable_reader = VoidSSTable::reader(&buffer[start..stop]);
+            assert!(sstable_reader.advance().unwrap());
+            assert_eq!(sstable_reader.key(), format!("{:08}", num_keys).as_bytes());
+            num_keys += 1;
+            while sstable_reader.key() != &block_meta.last_key[..] {
+                assert!(sstable_reader.advance().unwrap());
+                num_keys += 1;
+            }
+        }
+        assert_eq!(num_keys, 500_000);
+    }
+
+    fn multi_block_key(i: u64) -> String {
+        format!("{:08}-{:x}", i, i.wrapping_mul(2_654_435_761))
+    }
+
+    #[test]
+    fn test_read_several_blocks() {
+        const NUM_KEYS: u64 = 300_000;
+        let mut buffer = vec![];
+        {
+            let mut sstable_writer = VoidSSTable::writer(&mut buffer);
+            for i in 0..NUM_KEYS {
+                assert!(sstable_writer.write(multi_block_key(i).as_bytes(), &()).is_ok());
+            }
+            assert!(sstable_writer.finalize().is_ok());
+        }
+        assert!(buffer.len() > 3_000_000);
+        let mut sstable_reader = VoidSSTable::reader(&buffer[..]);
+        for i in 0..NUM_KEYS {
+            assert!(sstable_reader.advance().unwrap());
+            assert_eq!(sstable_reader.key(), multi_block_key(i).as_bytes());
+        }
+        assert!(!sstable_reader.advance().unwrap());
+        assert!(!sstable_reader.advance().unwrap());
+    }
+
+    #[test]
+    fn test_merge_several_blocks() {
+        let mut even = vec![];
+        let mut odd = vec![];
+        {
+            let mut even_writer = VoidSSTable::writer(&mut even);
+            let mut odd_writer = VoidSSTable::writer(&mut odd);
+            for i in 0..200_000 {
+                let key = multi_block_key(i);
+                if i % 2 == 0 {
+                    assert!(even_writer.write(key.as_bytes(), &()).is_ok());
+                } else {
+                    assert!(odd_writer.write(key.as_bytes(), &()).is_ok());
+                }
+            }
+            assert!(even_writer.finalize().is_ok());
+            assert!(odd_writer.finalize().is_ok());
+        }
+        let mut output = Vec::new();
+        assert!(VoidSSTable::merge(vec![&even[..], &odd[..]], &mut output, VoidMerge).is_ok());
+        let mut sstable_reader = VoidSSTable::reader(&output[..]);
+        for i in 0..200_000 {
+            assert!(sstable_reader.advance().unwrap());
+            assert_eq!(sstable_reader.key(), multi_block_key(i).as_bytes());
+        }
+        assert!(!sstable_reader.advance().unwrap());
+    }
+
+    #[test]
+    fn test_values_round_trip() {
+        let mut buffer = vec![];
+        {
+            let mut sstable_writer = U32SSTable::writer(&mut buffer);
+            for i in 0..200_000u32 {
+                assert!(sstable_writer.write(multi_block_key(u64::from(i)).as_bytes(), &(i * 7)).is_ok());
+            }
+            assert!(sstable_writer.finalize().is_ok());
+        }
+        let mut sstable_reader = U32SSTable::reader(&buffer[..]);
+        for i in 0..200_000u32 {
+            assert!(sstable_reader.advance().unwrap());
+            assert_eq!(sstable_reader.key(), multi_block_key(u64::from(i)).as_bytes());
+            assert_eq!(*sstable_reader.value(), i * 7);
+        }
+        assert!(!sstable_reader.advance().unwrap());
+        let table = U32SSTable::table(Cursor::new(&buffer[..])).unwrap();
+        assert!(table.index().blocks().len() > 1);
+        assert_eq!(table.get(multi_block_key(123_456).as_bytes()).unwrap(), Some(123_456 * 7));
+        assert_eq!(table.get(b"abc").unwrap(), None);
+    }
+
+    #[test]
+    fn test_merge_values() {
+        let mut left = vec![];
+        let mut right = vec![];
+        {
+            let mut left_writer = U32SSTable::writer(&mut left);
+            assert!(left_writer.write(b"abc", &1).is_ok());
+            assert!(left_writer.write(b"abd", &2).is_ok());
+            assert!(left_writer.finalize().is_ok());
+            let mut right_writer = U32SSTable::writer(&mut right);
+            assert!(right_writer.write(b"abc", &3).is_ok());
+            assert!(right_writer.write(b"b", &4).is_ok());
+            assert!(right_writer.finalize().is_ok());
+        }
+        let mut output = Vec::new();
+        assert!(U32SSTable::merge(vec![&left[..], &right[..]], &mut output, KeepFirst).is_ok());
+        let mut sstable_reader = U32SSTable::reader(&output[..]);
+        assert!(sstable_reader.advance().unwrap());
+        assert_eq!(sstable_reader.key(), b"abc");
+        assert_eq!(*sstable_reader.value(), 1);
+        assert!(sstable_reader.advance().unwrap());
+        assert_eq!(sstable_reader.key(), b"abd");
+        assert_eq!(*sstable_reader.value(), 2);
+        assert!(sstable_reader.advance().unwrap());
+        assert_eq!(sstable_reader.key(), b"b");
+        assert_eq!(*sstable_reader.value(), 4);
+        assert!(!sstable_reader.advance().unwrap());
+    }
+
+    fn write_u64_sstable(key_values: &[(&[u8], u64)]) -> Vec<u8> {
+        let mut buffer = vec![];
+        {
+            let mut sstable_writer = U64SSTable::writer(&mut buffer);
+            for &(key, val) in key_values {
+                assert!(sstable_writer.write(key, &val).is_ok());
+            }
+            assert!(sstable_writer.finalize().is_ok());
+        }
+        buffer
+    }
+
+    fn read_u64_sstable(buffer: &[u8]) -> Vec<(Vec<u8>, u64)> {
+        let mut sstable_reader = U64SSTable::reader(buffer);
+        let mut key_values = Vec::new();
+        while sstable_reader.advance().unwrap() {
+            key_values.push((sstable_reader.key().to_vec(), *sstable_reader.value()));
+        }
+        key_values
+    }
+
+    #[test]
+    fn test_u64_sstable_merge() {
+        let left = write_u64_sstable(&[(b"abc", 1), (b"abd", 200), (b"c", 3)]);
+        let right = write_u64_sstable(&[(b"abc", 1_000_000), (b"b", 4), (b"c", u64::from(u32::MAX))]);
+        let mut output = Vec::new();
+        assert!(U64SSTable::merge(vec![&left[..], &right[..]], &mut output, SumMerge).is_ok());
+        assert_eq!(read_u64_sstable(&output[..]), vec![
+            (b"abc".to_vec(), 1_000_001),
+            (b"abd".to_vec(), 200),
+            (b"b".to_vec(), 4),
+            (b"c".to_vec(), 3 + u64::from(u32::MAX))]);
+        let mut output = Vec::new();
+        assert!(U64SSTable::merge(vec![&left[..], &right[..]], &mut output, KeepFirst).is_ok());
+        assert_eq!(read_u64_sstable(&output[..]), vec![
+            (b"abc".to_vec(), 1),
+            (b"abd".to_vec(), 200),
+            (b"b".to_vec(), 4),
+            (b"c".to_vec(), 3)]);
+        assert_eq!(U64SSTable::get(Cursor::new(&output[..]), b"abd").unwrap(), Some(200));
+    }
+
+    #[test]
+    fn test_bytes_sstable() {
+        let mut left = vec![];
+        let mut right = vec![];
+        {
+            let mut left_writer = BytesSSTable::writer(&mut left);
+            assert!(left_writer.write(b"abc", b"hello").is_ok());
+            assert!(left_writer.write(b"abd", b"").is_ok());
+            assert!(left_writer.finalize().is_ok());
+            let mut right_writer = BytesSSTable::writer(&mut right);
+            assert!(right_writer.write(b"abc", b"world").is_ok());
+            assert!(right_writer.write(b"b", &[7u8; 1_000][..]).is_ok());
+            assert!(right_writer.finalize().is_ok());
+        }
+        let mut output = Vec::new();
+        assert!(BytesSSTable::merge(vec![&left[..], &right[..]], &mut output, KeepFirst).is_ok());
+        let mut sstable_reader = BytesSSTable::reader(&output[..]);
+        assert!(sstable_reader.advance().unwrap());
+        assert_eq!(sstable_reader.key(), b"abc");
+        assert_eq!(sstable_reader.value(), b"hello");
+        assert!(sstable_reader.advance().unwrap());
+        assert_eq!(sstable_reader.key(), b"abd");
+        assert_eq!(sstable_reader.value(), b"");
+        assert!(sstable_reader.advance().unwrap());
+        assert_eq!(sstable_reader.key(), b"b");
+        assert_eq!(sstable_reader.value(), &[7u8; 1_000][..]);
+        assert!(!sstable_reader.advance().unwrap());
+        assert_eq!(BytesSSTable::get(Cursor::new(&output[..]), b"abc").unwrap(), Some(b"hello".to_vec()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Counters {
+        count: u64,
+        deleted: bool,
+        offsets: Vec<u32>,
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_sstable() {
+        use super::SerdeSSTable;
+        let counters = |count: u64| Counters {
+            count,
+            deleted: count > 50_000,
+            offsets: vec![count as u32, 1_000_000],
+        };
+        let mut buffer = vec![];
+        {
+            let mut sstable_writer = SerdeSSTable::<Counters>::writer(&mut buffer);
+            for i in 0..100_000 {
+                assert!(sstable_writer.write(multi_block_key(i).as_bytes(), &counters(i)).is_ok());
+            }
+            assert!(sstable_writer.finalize().is_ok());
+        }
+        let mut sstable_reader = SerdeSSTable::<Counters>::reader(&buffer[..]);
+        assert_eq!(sstable_reader.value(), &Counters::default());
+        for i in 0..100_000 {
+            assert!(sstable_reader.advance().unwrap());
+            assert_eq!(sstable_reader.key(), multi_block_key(i).as_bytes());
+            assert_eq!(sstable_reader.value(), &counters(i));
+        }
+        assert!(!sstable_reader.advance().unwrap());
+        assert_eq!(SerdeSSTable::<Counters>::get(Cursor::new(&buffer[..]), multi_block_key(77_777).as_bytes()).unwrap(),
+                   Some(counters(77_777)));
+    }
+
+    #[test]
+    fn test_term_info_sstable() {
+        let term_info = |i: u64| TermInfo {
+            doc_freq: (i % 7) as u32 + 1,
+            postings_offset: i * 100,
+            positions_offset: i * 1_000 + 17,
+        };
+        let mut buffer = vec![];
+        {
+            let mut sstable_writer = TermInfoSSTable::writer(&mut buffer);
+            for i in 0..200_000 {
+                assert!(sstable_writer.write(multi_block_key(i).as_bytes(), &term_info(i)).is_ok());
+            }
+            assert!(sstable_writer.finalize().is_ok());
+        }
+        let mut sstable_reader = TermInfoSSTable::reader(&buffer[..]);
+        for i in 0..200_000 {
+            assert!(sstable_reader.advance().unwrap());
+            assert_eq!(sstable_reader.value(), &term_info(i));
+        }
+        assert!(!sstable_reader.advance().unwrap());
+        let table = TermInfoSSTable::table(Cursor::new(&buffer[..])).unwrap();
+        assert!(table.index().blocks().len() > 1);
+        for i in (0..200_000).step_by(9_973) {
+            assert_eq!(table.get(multi_block_key(i).as_bytes()).unwrap(), Some(term_info(i)));
+        }
+    }
+
+    #[test]
+    fn test_restart_points() {
+        let term_info = |i: u64| TermInfo {
+            doc_freq: (i % 7) as u32 + 1,
+            postings_offset: i * 100,
+            positions_offset: i * 1_000 + 17,
+        };
+        for &restart_interval in &[1, 3, 16, 100_000] {
+            let options = WriterOptions { restart_interval, ..WriterOptions::default() };
+            let mut term_info_buffer = vec![];
+            let mut bitpacked_buffer = vec![];
+            {
+                let mut term_info_writer = TermInfoSSTable::writer_with_options(&mut term_info_buffer, options.clone());
+                let mut bitpacked_writer = BitpackedU64SSTable::writer_with_options(&mut bitpacked_buffer, options);
+                for i in 0..20_000 {
+                    assert!(term_info_writer.write(multi_block_key(i).as_bytes(), &term_info(i)).is_ok());
+                    assert!(bitpacked_writer.write(multi_block_key(i).as_bytes(), &(i * 3)).is_ok());
+                }
+                assert!(term_info_writer.finalize().is_ok());
+                assert!(bitpacked_writer.finalize().is_ok());
+            }
+            let term_info_table = TermInfoSSTable::table(Cursor::new(&term_info_buffer[..])).unwrap();
+            let bitpacked_table = BitpackedU64SSTable::table(Cursor::new(&bitpacked_buffer[..])).unwrap();
+            assert!(term_info_table.index().blocks().len() < 5);
+            for i in (0..20_000).step_by(97).chain(Some(19_999)) {
+                let key = multi_block_key(i);
+                assert_eq!(term_info_table.get(key.as_bytes()).unwrap(), Some(term_info(i)));
+                assert_eq!(bitpacked_table.get(key.as_bytes()).unwrap(), Some(i * 3));
+                assert_eq!(bitpacked_table.key_to_ord(key.as_bytes()).unwrap(), Some(i));
+                assert_eq!(bitpacked_table.ord_to_key(i).unwrap(), Some(key.into_bytes()));
+            }
+            assert_eq!(term_info_table.get(b"00000017").unwrap(), None);
+            let mut reader = TermInfoSSTable::reader(&term_info_buffer[..]);
+            for i in (0..20_000).step_by(1_013) {
+                assert!(reader.seek(multi_block_key(i).as_bytes()).unwrap());
+                assert_eq!(reader.value(), &term_info(i));
+                assert!(reader.advance().unwrap());
+                assert_eq!(reader.value(), &term_info(i + 1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_bitpacked_u64_sstable() {
+        let mut buffer = vec![];
+        {
+            let mut sstable_writer = BitpackedU64SSTable::writer(&mut buffer);
+            for i in 0..300_000 {
+                assert!(sstable_writer.write(multi_block_key(i).as_bytes(), &(i * 3 % 1_000)).is_ok());
+            }
+            assert!(sstable_writer.finalize().is_ok());
+        }
+        let mut sstable_reader = BitpackedU64SSTable::reader(&buffer[..]);
+        for i in 0..300_000 {
+            assert!(sstable_reader.advance().unwrap());
+            assert_eq!(sstable_reader.key(), multi_block_key(i).as_bytes());
+            assert_eq!(*sstable_reader.value(), i * 3 % 1_000);
+        }
+        assert!(!sstable_reader.advance().unwrap());
+        let table = BitpackedU64SSTable::table(Cursor::new(&buffer[..])).unwrap();
+        assert!(table.index().blocks().len() > 1);
+        assert_eq!(table.get(multi_block_key(299_999).as_bytes()).unwrap(), Some(299_999 * 3 % 1_000));
+        let mut output = Vec::new();
+        assert!(BitpackedU64SSTable::merge(vec![&buffer[..], &buffer[..]], &mut output, SumMerge).is_ok());
+        assert_eq!(BitpackedU64SSTable::get(Cursor::new(&output[..]), multi_block_key(1_234).as_bytes()).unwrap(),
+                   Some(2 * (1_234 * 3 % 1_000)));
+    }
+
+    fn aux_test_options(options: WriterOptions) -> Vec<u8> {
+        let mut buffer = vec![];
+        {
+            let mut sstable_writer = U64SSTable::writer_with_options(&mut buffer, options);
+            for i in 0..300_000 {
+                assert!(sstable_writer.write(multi_block_key(i).as_bytes(), &i).is_ok());
+            }
+            assert!(sstable_writer.finalize().is_ok());
+        }
+        let shared_buffer: Arc<[u8]> = Arc::from(&buffer[..]);
+        let sstable_readers = vec![
+            U64SSTable::reader(&buffer[..]),
+            U64SSTable::reader_from_bytes(&buffer[..]),
+            U64SSTable::reader_from_shared_bytes(shared_buffer),
+        ];
+        for mut sstable_reader in sstable_readers {
+            for i in 0..300_000 {
+                assert!(sstable_reader.advance().unwrap());
+                assert_eq!(sstable_reader.key(), multi_block_key(i).as_bytes());
+                assert_eq!(*sstable_reader.value(), i);
+            }
+            assert!(!sstable_reader.advance().unwrap());
+        }
+        assert!(U64SSTable::verify(Cursor::new(&buffer[..])).is_ok());
+        let table = U64SSTable::table(Cursor::new(&buffer[..])).unwrap();
+        assert!(table.index().blocks().len() > 1);
+        assert_eq!(table.get(multi_block_key(200_000).as_bytes()).unwrap(), Some(200_000));
+        buffer
+    }
+
+    #[test]
+    fn test_no_compression_codec() {
+        aux_test_options(WriterOptions { codec: Arc::new(codec::NoCompression), ..WriterOptions::default() });
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_lz4_codec() {
+        aux_test_options(WriterOptions { codec: Arc::new(codec::Lz4), ..WriterOptions::default() });
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_codec() {
+        aux_test_options(WriterOptions { codec: Arc::new(codec::Zstd::default()), ..WriterOptions::default() });
+    }
+
+    #[test]
+    fn test_block_size() {
+        let small_blocks = aux_test_options(WriterOptions { block_size: 4_096, ..WriterOptions::default() });
+        let table = U64SSTable::table(Cursor::new(&small_blocks[..])).unwrap();
+        assert!(table.index().blocks().len() > 100);
+        assert!(table.index().blocks().iter().all(|block_meta| block_meta.num_bytes < 5_000));
+    }
+
+    #[test]
+    fn test_max_keys_per_block() {
+        let buffer = aux_test_options(WriterOptions { max_keys_per_block: Some(1_000), ..WriterOptions::default() });
+        let table = U64SSTable::table(Cursor::new(&buffer[..])).unwrap();
+        assert_eq!(table.index().blocks().len(), 300);
+        assert_eq!(&table.index().blocks()[0].last_key[..], multi_block_key(999).as_bytes());
+    }
+
+    #[test]
+    fn test_without_checksum() {
+        let with_checksum = aux_test_options(WriterOptions::default());
+        let without_checksum = aux_test_options(WriterOptions { checksum: false, ..WriterOptions::default() });
+        let num_blocks = U64SSTable::table(Cursor::new(&with_checksum[..])).unwrap().index().blocks().len();
+        assert_eq!(with_checksum.len() - without_checksum.len(), num_blocks * 4);
+    }
+
+    #[test]
+    fn test_empty_key() {
+        let mut buffer = vec![];
+        {
+            let mut delta_writer = VoidSSTable::delta_writer(&mut buffer);
+            assert!(delta_writer.write_delta(0, b"", &()).is_ok());
+            assert!(delta_writer.write_delta(0, b"a", &()).is_ok());
+            assert!(delta_writer.finalize().is_ok());
+        }
+        let mut sstable_reader = VoidSSTable::reader(&buffer[..]);
+        assert!(sstable_reader.advance().unwrap());
+        assert_eq!(sstable_reader.key(), b"");
+        assert!(sstable_reader.advance().unwrap());
+        assert_eq!(sstable_reader.key(), b"a");
+        assert!(!sstable_reader.advance().unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "The restart interval should fit in a u32.")]
+    #[cfg(target_pointer_width = "64")]
+    fn test_restart_interval_too_large() {
+        let options = WriterOptions { restart_interval: u32::MAX as usize + 1, ..WriterOptions::default() };
+        VoidSSTable::writer_with_options(vec![], options);
+    }
+
     #[test]
     #[should_panic]
     fn test_simple_sstable_non_increasing_key() {
@@ -7,10 +7,11 @@ use std::cmp::Ordering;
 use std::cmp::Ord;
 use std::option::Option::None;
 use std::mem;
+use std::borrow::Borrow;
 use std::fmt::Debug;
 use common_prefix_len;
 
-fn pick_lowest_with_ties<'a, 'b, T, FnKey: Fn(&'b T)->K, K>(elements: &'b [T], key: FnKey, ids: &'a mut [usize]) -> (&'a [usize], &'a [usize])
+fn pick_lowest_with_ties<'a, 'b, T, FnKey, K>(elements: &'b [T], key: FnKey, ids: &'a mut [usize]) -> (&'a [usize], &'a [usize])
     where
         FnKey: Fn(&'b T)->K,
         K: Ord + Debug + 'b {
@@ -44,8 +45,10 @@ fn pick_lowest_with_ties<'a, 'b, T, FnKey: Fn(&'b T)->K, K>(elements: &'b [T], k
 struct HeapItem(pub u32);
 
 impl HeapItem  {
+    // The heap pops the longest common prefix first, and
+    // for a given common prefix length, the smallest next byte.
     fn new(common_prefix_len: u32, next_byte: u8) -> Self {
-        HeapItem(common_prefix_len << 8 | (next_byte as u32))
+        HeapItem(common_prefix_len << 8 | (255u32 - next_byte as u32))
     }
 
     fn common_prefix_len(&self) -> usize {
@@ -70,13 +73,13 @@ impl Queue {
     fn push_to_queue(heap_item: HeapItem,
                      idx: usize,
                      queue: &mut BinaryHeap<u32>,
-                     map: &mut Vec<Vec<usize>>,
+                     map: &mut [Vec<usize>],
                      spares: &mut Vec<Vec<usize>>) {
         let heap_id = heap_item_to_id(&heap_item);
         let ids = &mut map[heap_id];
         if ids.is_empty() {
             queue.push(heap_item.0);
-            mem::replace(ids, spares.pop().unwrap());
+            *ids = spares.pop().unwrap();
         }
         ids.push(idx);
     }
@@ -100,7 +103,7 @@ impl Queue {
             .pop()
             .map(|heap_item| {
                 dest.clear();
-                let idx = mem::replace(&mut self.map[heap_item as usize], Vec::new());
+                let idx = mem::take(&mut self.map[heap_item as usize]);
                 self.spares.push(mem::replace(dest,idx));
                 HeapItem(heap_item)
         })
@@ -115,7 +118,7 @@ pub fn merge_sstable<SST: SSTable, W: io::Write, M: ValueMerger<SST::Value>>(
     let mut delta_writer = writer.into_delta_writer();
     let mut readers = vec![];
     let mut empty_key_values: Option<M::TSingleValueMerger> = None;
-    for mut reader in unstarted_readers {
+    for reader in unstarted_readers {
         let mut delta_reader = reader.into_delta_reader();
         if delta_reader.advance()? {
             if delta_reader.suffix().is_empty() {
@@ -136,7 +139,7 @@ pub fn merge_sstable<SST: SSTable, W: io::Write, M: ValueMerger<SST::Value>>(
         }
     }
     if let Some(value_merger) = empty_key_values {
-        delta_writer.write_delta(0, &[], &value_merger.finish())?;
+        delta_writer.write_delta(0, &[], value_merger.finish().borrow())?;
     }
 
     let mut queue = Queue::with_capacity(readers.len());
@@ -162,7 +165,7 @@ pub fn merge_sstable<SST: SSTable, W: io::Write, M: ValueMerger<SST::Value>>(
                 }
                 delta_writer.write_delta(heap_item.common_prefix_len(),
                                          suffix,
-                                         &single_value_merger.finish())?;
+                                         single_value_merger.finish().borrow())?;
             } else {
                 delta_writer.write_delta(heap_item.common_prefix_len(),
                                          suffix,
@@ -177,9 +180,19 @@ pub fn merge_sstable<SST: SSTable, W: io::Write, M: ValueMerger<SST::Value>>(
             }
         }
         for &tie_id in tie_ids {
-            let mut reader = &mut readers[tie_id];
+            let reader = &mut readers[tie_id];
             if reader.advance()? {
-                queue.register(reader.common_prefix_len() as u32, reader.suffix()[0], tie_id);
+                // Restart keys, including the first key of every block, are
+                // written in full with a common prefix length of 0, so their
+                // common prefix with the last written key has to be computed.
+                let extra_common_prefix_len = if reader.common_prefix_len() == 0 {
+                    common_prefix_len(delta_writer.last_key(), reader.suffix())
+                } else {
+                    0
+                };
+                queue.register((reader.common_prefix_len() + extra_common_prefix_len) as u32,
+                               reader.suffix()[extra_common_prefix_len],
+                               tie_id);
             }
         }
     }
@@ -4,6 +4,7 @@ use {SSTable, Reader, Writer};
 use super::SingleValueMerger;
 use super::ValueMerger;
 use std::io;
+use std::borrow::Borrow;
 use std::collections::BinaryHeap;
 use std::cmp::Ordering;
 use std::collections::binary_heap::PeekMut;
@@ -17,7 +18,7 @@ impl<B: AsRef<[u8]>> Ord for HeapItem<B> {
 }
 impl<B: AsRef<[u8]>> PartialOrd for HeapItem<B> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(other.0.as_ref().cmp(self.0.as_ref()))
+        Some(self.cmp(other))
     }
 }
 
@@ -63,7 +64,7 @@ pub fn merge_sstable<SST: SSTable, W: io::Write, M: ValueMerger<SST::Value>>(
             break;
         }
         let value = value_merger.finish();
-        writer.write_value(&value);
+        writer.write_value(value.borrow());
     }
     writer.finalize()?;
     Ok(())
@@ -5,12 +5,12 @@ pub use self::fast_merge::merge_sstable;
 pub use self::heap_merge::merge_sstable as merge_sstable_heap;
 
 
-pub trait SingleValueMerger<V> {
+pub trait SingleValueMerger<V: ToOwned + ?Sized> {
     fn add(&mut self, v: &V);
-    fn finish(self) -> V;
+    fn finish(self) -> V::Owned;
 }
 
-pub trait ValueMerger<V> {
+pub trait ValueMerger<V: ToOwned + ?Sized> {
     type TSingleValueMerger: SingleValueMerger<V>;
     fn new_value(&mut self, v: &V) -> Self::TSingleValueMerger;
 }
@@ -20,38 +20,56 @@ pub struct KeepFirst;
 
 pub struct FirstVal<V>(V);
 
-impl<V: Clone> ValueMerger<V> for KeepFirst {
-    type TSingleValueMerger = FirstVal<V>;
+impl<V: ToOwned + ?Sized> ValueMerger<V> for KeepFirst {
+    type TSingleValueMerger = FirstVal<V::Owned>;
 
-    fn new_value(&mut self, v: &V) -> FirstVal<V> {
-        FirstVal(v.clone())
+    fn new_value(&mut self, v: &V) -> FirstVal<V::Owned> {
+        FirstVal(v.to_owned())
     }
 }
 
-impl<V> SingleValueMerger<V> for FirstVal<V> {
+impl<V: ToOwned + ?Sized> SingleValueMerger<V> for FirstVal<V::Owned> {
     fn add(&mut self, _: &V) {}
 
-    fn finish(self) -> V {
+    fn finish(self) -> V::Owned {
         self.0
     }
 }
 
+/// Sums the values associated with a same key.
+#[derive(Default)]
+pub struct SumMerge;
+
+impl ValueMerger<u64> for SumMerge {
+    type TSingleValueMerger = u64;
+
+    fn new_value(&mut self, v: &u64) -> u64 {
+        *v
+    }
+}
+
+impl SingleValueMerger<u64> for u64 {
+    fn add(&mut self, v: &u64) {
+        *self += *v;
+    }
+
+    fn finish(self) -> u64 {
+        self
+    }
+}
+
 pub struct VoidMerge;
 impl ValueMerger<()> for VoidMerge {
 
     type TSingleValueMerger = ();
 
-    fn new_value(&mut self, _: &()) -> () {
-        ()
-    }
+    fn new_value(&mut self, _: &()) {}
 }
 
 impl SingleValueMerger<()> for () {
     fn add(&mut self, _: &()) {}
 
-    fn finish(self) -> () {
-        ()
-    }
+    fn finish(self) {}
 }
 
 #[cfg(test)]
@@ -59,14 +77,15 @@ mod tests {
 
     use VoidSSTable;
     use SSTable;
-    use super::VoidMerge;
+    use WriterOptions;
+    use super::{VoidMerge, merge_sstable};
     use std::str;
     use std::collections::BTreeSet;
 
-    fn write_sstable(keys: &[&'static str]) -> Vec<u8> {
+    fn write_sstable(keys: &[&str], options: WriterOptions) -> Vec<u8> {
         let mut buffer: Vec<u8> = vec![];
         {
-            let mut sstable_writer = VoidSSTable::writer(&mut buffer);
+            let mut sstable_writer = VoidSSTable::writer_with_options(&mut buffer, options);
             for &key in keys {
                 assert!(sstable_writer.write(key.as_bytes(), &()).is_ok());
             }
@@ -75,10 +94,13 @@ mod tests {
         buffer
     }
 
-    fn merge_test_aux(arrs: &[&[&'static str]]) {
+    fn merge_test_aux(arrs: &[&[&str]]) {
+        merge_test_aux_with_options(arrs, WriterOptions::default())
+    }
+
+    fn merge_test_aux_with_options(arrs: &[&[&str]], options: WriterOptions) {
         let sstables = arrs.iter()
-            .cloned()
-            .map(write_sstable)
+            .map(|keys| write_sstable(keys, options.clone()))
             .collect::<Vec<_>>();
         let sstables_ref: Vec<&[u8]> = sstables.iter()
             .map(|s| s.as_ref())
@@ -90,7 +112,17 @@ mod tests {
             }
         }
         let mut w = Vec::new();
-        assert!(VoidSSTable::merge(sstables_ref, &mut w, VoidMerge).is_ok());
+        {
+            let readers = sstables_ref.into_iter().map(VoidSSTable::reader).collect();
+            let writer = VoidSSTable::writer_with_options(&mut w, options);
+            assert!(merge_sstable::<VoidSSTable, _, _>(readers, writer, VoidMerge).is_ok());
+        }
+        let mut reader = VoidSSTable::reader(&w[..]);
+        for key in merged {
+            assert!(reader.advance().unwrap());
+            assert_eq!(reader.key(), key.as_bytes());
+        }
+        assert!(!reader.advance().unwrap());
     }
 
     #[test]
@@ -108,5 +140,23 @@ mod tests {
         merge_test_aux(&[&["a"]]);
         merge_test_aux(&[&["a","b"], &["ab"]]);
         merge_test_aux(&[&["a","b"], &["a", "b"]]);
+        merge_test_aux(&[&["00-a", "02-c"], &["01-b"]]);
+    }
+
+    #[test]
+    fn test_merge_restart_intervals() {
+        let keys: Vec<String> = (0..3_000).map(|i| format!("key{:06}", i * 7)).collect();
+        let arrs: Vec<Vec<&str>> = (0..3)
+            .map(|r| keys.iter()
+                .enumerate()
+                .filter(|&(i, _)| i % 3 == r || i % 5 == 0)
+                .map(|(_, key)| key.as_str())
+                .collect())
+            .collect();
+        let arrs_ref: Vec<&[&str]> = arrs.iter().map(|arr| &arr[..]).collect();
+        for &restart_interval in &[1, 100_000] {
+            let options = WriterOptions { restart_interval, block_size: 256, ..WriterOptions::default() };
+            merge_test_aux_with_options(&arrs_ref, options);
+        }
     }
 }
\ No newline at end of file
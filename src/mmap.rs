@@ -0,0 +1,198 @@
+use std::fs::File;
+use std::io;
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+use memmap2::Mmap;
+use footer::SSTableMetadata;
+use sstable_index::SSTableIndex;
+use table::{self, Table};
+use read_at;
+use automaton::{Automaton, SearchReader};
+use {SSTable, Reader, BlockReader, BlockCache};
+
+/// An sstable file mapped in memory, opened with `SSTable::open`.
+///
+/// The handle is cheap to clone and can be shared between threads. Unlike
+/// those of `Table`, its readers are `'static`. They read the blocks
+/// directly from the mapping: only compressed blocks are copied, when they
+/// are decompressed, unless a block cache is set.
+pub struct MmapTable<TSSTable> {
+    table: Arc<Table<TSSTable, Arc<Mmap>>>,
+}
+
+impl<TSSTable> Clone for MmapTable<TSSTable> {
+    fn clone(&self) -> Self {
+        MmapTable {
+            table: self.table.clone(),
+        }
+    }
+}
+
+impl<TSSTable: SSTable> MmapTable<TSSTable> {
+
+    /// Maps the file at `path`, and loads its footer, metadata, block
+    /// index and bloom filter.
+    ///
+    /// The file must not be modified while it is mapped.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // Safety: sstables are never modified once written.
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(MmapTable {
+            table: Arc::new(Table::open(Arc::new(mmap))?),
+        })
+    }
+
+    /// Caches the blocks decoded by the lookups and by the readers of the
+    /// handle in `block_cache`, which may be shared with other tables.
+    ///
+    /// The clones of the handle made before the call keep the previous cache.
+    pub fn with_block_cache(self, block_cache: Arc<BlockCache>) -> Self {
+        let table = Arc::try_unwrap(self.table).unwrap_or_else(|table| table.shallow_clone());
+        MmapTable {
+            table: Arc::new(table.with_block_cache(block_cache)),
+        }
+    }
+
+    /// Returns the underlying table, whose readers borrow the handle.
+    pub fn table(&self) -> &Table<TSSTable, Arc<Mmap>> {
+        &self.table
+    }
+
+    pub fn metadata(&self) -> &SSTableMetadata {
+        self.table.metadata()
+    }
+
+    pub fn index(&self) -> &SSTableIndex {
+        self.table.index()
+    }
+
+    /// Returns `false` if the sstable definitely does not contain `key`.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.table.may_contain(key)
+    }
+
+    fn block_reader(&self, offset: u64, num_bytes: u64) -> BlockReader<'static> {
+        if let Some(ref block_cache) = self.table.block_cache {
+            let (index, source, id, block_cache) = (self.table.index.clone(), self.table.source.clone(), self.table.id, block_cache.clone());
+            return BlockReader::from_blocks(Box::new(move |block_id| table::load_block(&index, &source, id, Some(&block_cache), block_id)));
+        }
+        read_at::into_block_reader(self.table.source.clone(), offset, num_bytes)
+    }
+
+    /// Returns a reader over all of the keys of the sstable.
+    pub fn reader(&self) -> io::Result<Reader<'static, TSSTable::Reader>> {
+        self.range(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// Returns a reader over the keys within `lower` and `upper`.
+    pub fn range(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> io::Result<Reader<'static, TSSTable::Reader>> {
+        table::range_reader::<TSSTable, _>(&self.table.index, &self.table.footer,
+                                           |offset, num_bytes| Ok(self.block_reader(offset, num_bytes)),
+                                           lower, upper)
+    }
+
+    /// Returns a reader over the keys starting with `prefix`.
+    pub fn prefix(&self, prefix: &[u8]) -> io::Result<Reader<'static, TSSTable::Reader>> {
+        let mut reader = self.range(Bound::Included(prefix), Bound::Unbounded)?;
+        reader.set_prefix(prefix);
+        Ok(reader)
+    }
+
+    /// Returns a reader over the keys accepted by `automaton`.
+    pub fn search<A: Automaton>(&self, automaton: A) -> io::Result<SearchReader<'static, TSSTable::Reader, A>> {
+        Ok(SearchReader::new(self.reader()?, automaton))
+    }
+
+    /// Returns the value associated with `key`, if any.
+    pub fn get(&self, key: &[u8]) -> io::Result<Option<<TSSTable::Value as ToOwned>::Owned>> {
+        self.table.get(key)
+    }
+
+    /// Checks the checksums of all of the blocks, without decoding them.
+    pub fn verify(&self) -> io::Result<()> {
+        self.table.verify()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::ops::Bound;
+    use std::sync::Arc;
+    use std::thread;
+    use tempfile::NamedTempFile;
+    use {SSTable, U64SSTable, BlockCache};
+    use table::tests::{key, small_block_u64_table};
+    use super::MmapTable;
+
+    #[test]
+    fn test_mmap_table() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&small_block_u64_table()).unwrap();
+        let table: MmapTable<U64SSTable> = U64SSTable::open(file.path()).unwrap();
+        assert!(table.index().blocks().len() > 10);
+        assert_eq!(table.metadata().num_keys, 20_000);
+        assert!(table.verify().is_ok());
+        let threads: Vec<_> = (0..4u64)
+            .map(|thread_id| {
+                let table = table.clone();
+                thread::spawn(move || {
+                    for i in (thread_id..20_000).step_by(97) {
+                        assert_eq!(table.get(key(i).as_bytes()).unwrap(), Some(i));
+                    }
+                    assert_eq!(table.get(b"key00000001").unwrap(), None);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let mut reader = table.range(Bound::Included(key(10).as_bytes()), Bound::Excluded(key(13).as_bytes())).unwrap();
+        let mut vals = Vec::new();
+        while reader.advance().unwrap() {
+            vals.push(*reader.value());
+        }
+        assert_eq!(vals, vec![10, 11, 12]);
+        let mut reader = table.reader().unwrap();
+        drop(table);
+        for i in 0..20_000 {
+            assert!(reader.advance().unwrap());
+            assert_eq!(reader.key(), key(i).as_bytes());
+            assert_eq!(reader.ord(), i);
+        }
+        assert!(!reader.advance().unwrap());
+    }
+
+    #[test]
+    fn test_mmap_table_block_cache() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&small_block_u64_table()).unwrap();
+        let block_cache = Arc::new(BlockCache::new(10_000_000));
+        let uncached_table: MmapTable<U64SSTable> = U64SSTable::open(file.path()).unwrap();
+        let table = uncached_table.clone().with_block_cache(block_cache.clone());
+        assert_eq!(table.get(key(100).as_bytes()).unwrap(), Some(100));
+        assert_eq!(uncached_table.get(key(100).as_bytes()).unwrap(), Some(100));
+        assert_eq!(block_cache.num_misses(), 1);
+        let num_blocks = table.index().blocks().len() as u64;
+        let mut reader = table.reader().unwrap();
+        drop(table);
+        for i in 0..20_000 {
+            assert!(reader.advance().unwrap());
+            assert_eq!(reader.key(), key(i).as_bytes());
+        }
+        assert!(!reader.advance().unwrap());
+        assert_eq!(block_cache.num_misses(), num_blocks);
+        assert_eq!(block_cache.num_hits(), 1);
+    }
+
+    #[test]
+    fn test_mmap_table_invalid() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&[0u8; 100]).unwrap();
+        assert!(U64SSTable::open(file.path()).is_err());
+        assert!(U64SSTable::open(file.path().with_extension("missing")).is_err());
+    }
+}
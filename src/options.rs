@@ -0,0 +1,49 @@
+use std::sync::Arc;
+use codec::{BlockCodec, NoCompression};
+use bloom::BloomFilterOptions;
+
+/// Default value of `WriterOptions::block_size`.
+pub const DEFAULT_BLOCK_SIZE: usize = 256_000;
+
+/// Default value of `WriterOptions::restart_interval`.
+pub const DEFAULT_RESTART_INTERVAL: usize = 16;
+
+/// Settings of a `Writer` or of a `DeltaWriter`.
+///
+/// None of them need to be known to read the sstable back: blocks
+/// record their codec, whether they carry a checksum, and their restart points.
+///
+/// Small blocks make point lookups cheaper, large blocks compress better
+/// and are faster to scan.
+#[derive(Clone)]
+pub struct WriterOptions {
+    /// A block is flushed as soon as its keys and values exceed this
+    /// number of bytes, before compression.
+    pub block_size: usize,
+    /// If set, a block is also flushed as soon as it contains this number of keys.
+    pub max_keys_per_block: Option<usize>,
+    /// Every `restart_interval` keys, the key is written in full rather than
+    /// delta-encoded, so that readers can binary search the block.
+    ///
+    /// Must be greater than 0, and fit in a u32.
+    pub restart_interval: usize,
+    /// Codec used to compress the blocks.
+    pub codec: Arc<dyn BlockCodec + Send + Sync>,
+    /// If `true`, blocks end with the crc32c checksum of their content.
+    pub checksum: bool,
+    /// If set, a bloom filter over all of the keys is stored in the sstable.
+    pub bloom_filter: Option<BloomFilterOptions>,
+}
+
+impl Default for WriterOptions {
+    fn default() -> WriterOptions {
+        WriterOptions {
+            block_size: DEFAULT_BLOCK_SIZE,
+            max_keys_per_block: None,
+            restart_interval: DEFAULT_RESTART_INTERVAL,
+            codec: Arc::new(NoCompression),
+            checksum: true,
+            bloom_filter: None,
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::sync::Arc;
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use BlockReader;
+
+/// Storage an sstable is read from, at arbitrary offsets.
+///
+/// Reads only borrow the storage, so that any number of readers can share
+/// it, possibly from different threads, without seeking it.
+pub trait ReadAt {
+
+    /// Reads bytes starting at `offset` into `buf`, and returns the number
+    /// of bytes read.
+    ///
+    /// Returns `0` only if `buf` is empty or `offset` is at the end of the storage.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+
+    /// Returns the length of the storage.
+    fn num_bytes(&self) -> io::Result<u64>;
+
+    /// Returns the content of the storage if it is held in memory, so that
+    /// blocks can be read in place rather than copied.
+    fn as_bytes(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Reads exactly `buf.len()` bytes starting at `offset`.
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(buf, offset) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "sstable is truncated")),
+                Ok(num_bytes) => {
+                    buf = &mut buf[num_bytes..];
+                    offset += num_bytes as u64;
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_bytes_at(data: &[u8], buf: &mut [u8], offset: u64) -> usize {
+    if offset >= data.len() as u64 {
+        return 0;
+    }
+    let data = &data[offset as usize..];
+    let num_bytes = data.len().min(buf.len());
+    buf[..num_bytes].copy_from_slice(&data[..num_bytes]);
+    num_bytes
+}
+
+impl ReadAt for [u8] {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        Ok(read_bytes_at(self, buf, offset))
+    }
+
+    fn num_bytes(&self) -> io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        Some(self)
+    }
+}
+
+impl ReadAt for Vec<u8> {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        Ok(read_bytes_at(self, buf, offset))
+    }
+
+    fn num_bytes(&self) -> io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        Some(&self[..])
+    }
+}
+
+/// Reads the whole underlying buffer, regardless of the position of the cursor.
+impl<T: AsRef<[u8]>> ReadAt for Cursor<T> {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        Ok(read_bytes_at(self.get_ref().as_ref(), buf, offset))
+    }
+
+    fn num_bytes(&self) -> io::Result<u64> {
+        Ok(self.get_ref().as_ref().len() as u64)
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        Some(self.get_ref().as_ref())
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl ReadAt for Mmap {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        Ok(read_bytes_at(self, buf, offset))
+    }
+
+    fn num_bytes(&self) -> io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        Some(&self[..])
+    }
+}
+
+/// Positional reads, which do not move the position of the file.
+#[cfg(any(unix, windows))]
+impl ReadAt for File {
+    #[cfg(unix)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        ::std::os::unix::fs::FileExt::read_at(self, buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        ::std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+    }
+
+    fn num_bytes(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+
+    fn num_bytes(&self) -> io::Result<u64> {
+        (**self).num_bytes()
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        (**self).as_bytes()
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Arc<T> {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+
+    fn num_bytes(&self) -> io::Result<u64> {
+        (**self).num_bytes()
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        (**self).as_bytes()
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Box<T> {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+
+    fn num_bytes(&self) -> io::Result<u64> {
+        (**self).num_bytes()
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        (**self).as_bytes()
+    }
+}
+
+/// Sequential reader over the bytes `offset..end` of a `ReadAt`.
+pub(crate) struct ReadAtReader<R> {
+    source: R,
+    offset: u64,
+    end: u64,
+}
+
+impl<R: ReadAt> ReadAtReader<R> {
+    pub fn new(source: R, offset: u64, num_bytes: u64) -> ReadAtReader<R> {
+        ReadAtReader {
+            source,
+            offset,
+            end: offset.saturating_add(num_bytes),
+        }
+    }
+
+    /// Moves `num_bytes` bytes forward, without reading them.
+    pub fn skip(&mut self, num_bytes: u64) -> io::Result<()> {
+        if num_bytes > self.end - self.offset {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "sstable is truncated"));
+        }
+        self.offset += num_bytes;
+        Ok(())
+    }
+}
+
+impl<R: ReadAt> Read for ReadAtReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = (self.end - self.offset).min(buf.len() as u64) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+        let num_bytes = self.source.read_at(&mut buf[..len], self.offset)?;
+        self.offset += num_bytes as u64;
+        Ok(num_bytes)
+    }
+}
+
+/// Returns a block reader over the `num_bytes` bytes of `source` starting
+/// at `offset`.
+///
+/// The blocks are read in place if `source` is held in memory.
+pub(crate) fn block_reader<R: ReadAt + ?Sized>(source: &R, offset: u64, num_bytes: u64) -> BlockReader<'_> {
+    if let Some(data) = source.as_bytes() {
+        let len = data.len() as u64;
+        let start = offset.min(len) as usize;
+        let end = offset.saturating_add(num_bytes).min(len) as usize;
+        return BlockReader::from_bytes(&data[start..end]);
+    }
+    BlockReader::from_read_at(Box::new(source), offset, num_bytes)
+}
+
+/// Same as `block_reader`, but the block reader takes ownership of `source`.
+pub(crate) fn into_block_reader<'a, R: ReadAt + 'a>(source: R, offset: u64, num_bytes: u64) -> BlockReader<'a> {
+    if let Some(len) = source.as_bytes().map(|data| data.len() as u64) {
+        let start = offset.min(len) as usize;
+        let end = offset.saturating_add(num_bytes).min(len) as usize;
+        return BlockReader::from_owned_bytes(Box::new(source), start..end);
+    }
+    BlockReader::from_read_at(Box::new(source), offset, num_bytes)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::fs::File;
+    use std::io::{self, Cursor, Read, Write};
+    use std::ops::Bound;
+    use std::sync::Arc;
+    use std::thread;
+    use tempfile::NamedTempFile;
+    use {SSTable, U64SSTable};
+    use table::tests::{key, small_block_u64_table};
+    use super::{ReadAt, ReadAtReader};
+
+    fn aux_test_read_at<R: ReadAt + ?Sized>(source: &R, data: &[u8]) {
+        assert_eq!(source.num_bytes().unwrap(), data.len() as u64);
+        let mut buf = [0u8; 10];
+        source.read_exact_at(&mut buf[..], 5).unwrap();
+        assert_eq!(&buf[..], &data[5..15]);
+        assert_eq!(source.read_at(&mut buf[..], data.len() as u64).unwrap(), 0);
+        assert!(source.read_exact_at(&mut buf[..], data.len() as u64 - 5).is_err());
+        let mut read = Vec::new();
+        ReadAtReader::new(source, 3, 20).read_to_end(&mut read).unwrap();
+        assert_eq!(&read[..], &data[3..23]);
+    }
+
+    #[test]
+    fn test_read_at() {
+        let data: Vec<u8> = (0..100u8).collect();
+        aux_test_read_at(&data[..], &data[..]);
+        aux_test_read_at(&data, &data[..]);
+        aux_test_read_at(&Cursor::new(&data[..]), &data[..]);
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&data[..]).unwrap();
+        aux_test_read_at(file.as_file(), &data[..]);
+    }
+
+    /// Storage that is not held in memory, and counts the bytes read from it.
+    struct CountingReadAt {
+        data: Vec<u8>,
+        num_bytes_read: Cell<u64>,
+    }
+
+    impl ReadAt for CountingReadAt {
+        fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+            let num_bytes = self.data.read_at(buf, offset)?;
+            self.num_bytes_read.set(self.num_bytes_read.get() + num_bytes as u64);
+            Ok(num_bytes)
+        }
+
+        fn num_bytes(&self) -> io::Result<u64> {
+            self.data.num_bytes()
+        }
+    }
+
+    #[test]
+    fn test_seek_does_not_read_skipped_blocks() {
+        let source = CountingReadAt { data: small_block_u64_table(), num_bytes_read: Cell::new(0) };
+        let table = U64SSTable::table(&source).unwrap();
+        let max_block_num_bytes = table.index().blocks().iter().map(|block_meta| block_meta.num_bytes).max().unwrap();
+        source.num_bytes_read.set(0);
+        let mut reader = table.range(Bound::Unbounded, Bound::Unbounded).unwrap();
+        assert!(reader.seek(key(19_990).as_bytes()).unwrap());
+        assert_eq!(*reader.value(), 19_990);
+        assert!(source.num_bytes_read.get() <= max_block_num_bytes);
+        drop(reader);
+        source.num_bytes_read.set(0);
+        let mut reader = table.into_range(Bound::Included(key(19_990).as_bytes()), Bound::Unbounded).unwrap();
+        assert!(reader.advance().unwrap());
+        assert_eq!(*reader.value(), 19_990);
+        assert!(source.num_bytes_read.get() <= max_block_num_bytes);
+    }
+
+    #[test]
+    fn test_table_shared_between_threads() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&small_block_u64_table()).unwrap();
+        let table = Arc::new(U64SSTable::table(File::open(file.path()).unwrap()).unwrap());
+        assert!(table.index().blocks().len() > 10);
+        let threads: Vec<_> = (0..4u64)
+            .map(|thread_id| {
+                let table = table.clone();
+                thread::spawn(move || {
+                    for i in (thread_id..20_000).step_by(97) {
+                        assert_eq!(table.get(key(i).as_bytes()).unwrap(), Some(i));
+                        assert_eq!(table.key_to_ord(key(i).as_bytes()).unwrap(), Some(i));
+                    }
+                    let mut reader = table.prefix(b"key0001").unwrap();
+                    let mut num_keys = 0;
+                    while reader.advance().unwrap() {
+                        num_keys += 1;
+                    }
+                    assert_eq!(num_keys, 3_333);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(table.verify().is_ok());
+        let mut reader = U64SSTable::range(File::open(file.path()).unwrap(), Bound::Unbounded, Bound::Unbounded).unwrap();
+        for i in 0..20_000 {
+            assert!(reader.advance().unwrap());
+            assert_eq!(reader.key(), key(i).as_bytes());
+        }
+        assert!(!reader.advance().unwrap());
+    }
+}
@@ -0,0 +1,128 @@
+use std::borrow::Borrow;
+use std::io;
+use std::ops::Bound;
+use read_at::ReadAt;
+use value::ValueReader;
+use {Table, SSTable, to_owned_bound, above_lower_bound, below_upper_bound};
+
+/// Reads the keys of an sstable in decreasing order.
+///
+/// Blocks can only be decoded forward, so the reader goes through the
+/// blocks backwards, using the block index, and decodes each of them into
+/// a buffer before walking it in reverse.
+///
+/// Created with `Table::reverse_range` or `Table::reverse_prefix`. The
+/// blocks go through the block cache of the table, if any.
+pub struct ReverseReader<'a, TSSTable: SSTable, R> {
+    table: &'a Table<TSSTable, R>,
+    /// Blocks `0..num_remaining_blocks` have not been read yet.
+    num_remaining_blocks: usize,
+    keys: Vec<u8>,
+    key_ends: Vec<usize>,
+    values: Vec<<TSSTable::Value as ToOwned>::Owned>,
+    /// Entries `0..cursor` of the current block have not been returned yet.
+    cursor: usize,
+    lower_bound: Bound<Vec<u8>>,
+    upper_bound: Bound<Vec<u8>>,
+    finished: bool,
+    /// Provides the value returned when the reader is not positioned on a key.
+    default_value_reader: TSSTable::Reader,
+}
+
+impl<'a, TSSTable, R> ReverseReader<'a, TSSTable, R>
+    where TSSTable: SSTable, R: ReadAt {
+
+    pub(crate) fn new(table: &'a Table<TSSTable, R>, lower: Bound<&[u8]>, upper: Bound<&[u8]>)
+        -> ReverseReader<'a, TSSTable, R> {
+        let index = table.index();
+        // Blocks after the one that may contain the upper bound are skipped.
+        let num_remaining_blocks = match upper {
+            Bound::Included(key) | Bound::Excluded(key) =>
+                index.search(key).map(|block_id| block_id + 1).unwrap_or(index.blocks().len()),
+            Bound::Unbounded => index.blocks().len(),
+        };
+        ReverseReader {
+            table,
+            num_remaining_blocks,
+            keys: Vec::new(),
+            key_ends: Vec::new(),
+            values: Vec::new(),
+            cursor: 0,
+            lower_bound: to_owned_bound(lower),
+            upper_bound: to_owned_bound(upper),
+            finished: false,
+            default_value_reader: TSSTable::Reader::default(),
+        }
+    }
+
+    fn load_block(&mut self, block_id: usize) -> io::Result<()> {
+        self.keys.clear();
+        self.key_ends.clear();
+        self.values.clear();
+        let block_meta = &self.table.index().blocks()[block_id];
+        let mut reader = self.table.block_reader(block_id);
+        // The reader is limited to the block, and must not read past its last key.
+        while reader.advance()? {
+            self.keys.extend_from_slice(reader.key());
+            self.key_ends.push(self.keys.len());
+            self.values.push(reader.value().to_owned());
+            if reader.key() == &block_meta.last_key[..] {
+                break;
+            }
+        }
+        self.cursor = self.values.len();
+        Ok(())
+    }
+
+    pub fn advance(&mut self) -> io::Result<bool> {
+        loop {
+            if self.finished {
+                return Ok(false);
+            }
+            if self.cursor == 0 {
+                if self.num_remaining_blocks == 0 {
+                    self.finished = true;
+                    return Ok(false);
+                }
+                self.num_remaining_blocks -= 1;
+                let block_id = self.num_remaining_blocks;
+                self.load_block(block_id)?;
+                continue;
+            }
+            self.cursor -= 1;
+            if !below_upper_bound(self.key(), &self.upper_bound) {
+                continue;
+            }
+            if !above_lower_bound(self.key(), &self.lower_bound) {
+                self.finished = true;
+                return Ok(false);
+            }
+            return Ok(true);
+        }
+    }
+
+    /// Returns `false` before the first call to `advance`, and once it has
+    /// returned `false`.
+    fn has_entry(&self) -> bool {
+        !self.finished && self.cursor < self.values.len()
+    }
+
+    /// Returns the current key, or an empty key if the reader is not
+    /// positioned on a key.
+    pub fn key(&self) -> &[u8] {
+        if !self.has_entry() {
+            return &[];
+        }
+        let start = if self.cursor == 0 { 0 } else { self.key_ends[self.cursor - 1] };
+        &self.keys[start..self.key_ends[self.cursor]]
+    }
+
+    /// Returns the current value, or the default value of the value reader
+    /// if the reader is not positioned on a key.
+    pub fn value(&self) -> &TSSTable::Value {
+        if !self.has_entry() {
+            return self.default_value_reader.value(&[]);
+        }
+        self.values[self.cursor].borrow()
+    }
+}
@@ -0,0 +1,120 @@
+use std::io;
+use vint;
+
+/// Location of a block within the sstable file, together with
+/// the last key it contains.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlockMeta {
+    /// Last key of the block.
+    pub last_key: Vec<u8>,
+    /// Offset of the block, including its 4 bytes length header.
+    pub offset: u64,
+    /// Number of bytes of the block, including its 4 bytes length header.
+    pub num_bytes: u64,
+    /// Ordinal of the first key of the block.
+    pub first_ord: u64,
+}
+
+/// Index of the blocks of an sstable.
+///
+/// It is written after the blocks by `DeltaWriter::finalize` and makes it
+/// possible to identify the only block that may contain a given key.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SSTableIndex {
+    blocks: Vec<BlockMeta>,
+}
+
+impl SSTableIndex {
+
+    pub(crate) fn push(&mut self, block_meta: BlockMeta) {
+        self.blocks.push(block_meta);
+    }
+
+    pub fn blocks(&self) -> &[BlockMeta] {
+        &self.blocks[..]
+    }
+
+    /// Returns the ordinal of the only block that may contain `key`,
+    /// or `None` if `key` is greater than all of the keys of the sstable.
+    pub fn search(&self, key: &[u8]) -> Option<usize> {
+        let block_id = self.blocks
+            .partition_point(|block_meta| &block_meta.last_key[..] < key);
+        if block_id < self.blocks.len() {
+            Some(block_id)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the ordinal of the block containing the key of ordinal `ord`,
+    /// assuming `ord` is lower than the number of keys of the sstable.
+    ///
+    /// Returns `None` if the sstable has no block.
+    pub fn search_ord(&self, ord: u64) -> Option<usize> {
+        let num_blocks = self.blocks
+            .partition_point(|block_meta| block_meta.first_ord <= ord);
+        num_blocks.checked_sub(1)
+    }
+
+    pub fn serialize(&self, output: &mut Vec<u8>) {
+        vint::serialize_into_vec(self.blocks.len() as u64, output);
+        for block_meta in &self.blocks {
+            vint::write_bytes(&block_meta.last_key, output);
+            vint::serialize_into_vec(block_meta.offset, output);
+            vint::serialize_into_vec(block_meta.num_bytes, output);
+            vint::serialize_into_vec(block_meta.first_ord, output);
+        }
+    }
+
+    pub fn deserialize(mut data: &[u8]) -> io::Result<SSTableIndex> {
+        let num_blocks = vint::read_u64(&mut data)? as usize;
+        let mut blocks = Vec::with_capacity(num_blocks.min(data.len()));
+        for _ in 0..num_blocks {
+            let last_key = vint::read_bytes(&mut data)?.to_vec();
+            let offset = vint::read_u64(&mut data)?;
+            let num_bytes = vint::read_u64(&mut data)?;
+            let first_ord = vint::read_u64(&mut data)?;
+            blocks.push(BlockMeta { last_key, offset, num_bytes, first_ord });
+        }
+        Ok(SSTableIndex { blocks })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockMeta, SSTableIndex};
+
+    fn block_meta(last_key: &[u8], offset: u64, num_bytes: u64, first_ord: u64) -> BlockMeta {
+        BlockMeta { last_key: last_key.to_vec(), offset, num_bytes, first_ord }
+    }
+
+    #[test]
+    fn test_sstable_index_serialization() {
+        let mut index = SSTableIndex::default();
+        index.push(block_meta(b"abc", 0, 1_000, 0));
+        index.push(block_meta(b"abd", 1_000, 300_000, 2_000));
+        let mut buffer = Vec::new();
+        index.serialize(&mut buffer);
+        assert_eq!(SSTableIndex::deserialize(&buffer[..]).unwrap(), index);
+        assert!(SSTableIndex::deserialize(&buffer[..buffer.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_sstable_index_search() {
+        let mut index = SSTableIndex::default();
+        assert_eq!(index.search(b"a"), None);
+        assert_eq!(index.search_ord(0), None);
+        index.push(block_meta(b"c", 0, 10, 0));
+        index.push(block_meta(b"f", 10, 10, 3));
+        assert_eq!(index.search(b""), Some(0));
+        assert_eq!(index.search(b"c"), Some(0));
+        assert_eq!(index.search(b"ca"), Some(1));
+        assert_eq!(index.search(b"f"), Some(1));
+        assert_eq!(index.search(b"fa"), None);
+        assert_eq!(index.search_ord(0), Some(0));
+        assert_eq!(index.search_ord(2), Some(0));
+        assert_eq!(index.search_ord(3), Some(1));
+        assert_eq!(index.search_ord(4), Some(1));
+    }
+}
@@ -0,0 +1,793 @@
+use std::io;
+use std::marker::PhantomData;
+use std::ops::Bound;
+use std::sync::Arc;
+use sstable_index::SSTableIndex;
+use bloom::BloomFilter;
+use block_cache::{self, BlockCache};
+use footer::{Footer, SSTableMetadata};
+use read_at::{self, ReadAt};
+use {SSTable, Reader, BlockReader, ReverseReader};
+use automaton::{Automaton, SearchReader};
+
+/// An sstable opened for random access.
+///
+/// Opening the table loads its metadata and its block index, so that
+/// point lookups only need to read and decode a single block.
+///
+/// All of the reads go through `ReadAt`, so that a table can be shared
+/// between threads, each running lookups or creating readers.
+pub struct Table<TSSTable, R> {
+    pub(crate) footer: Footer,
+    pub(crate) metadata: SSTableMetadata,
+    pub(crate) index: Arc<SSTableIndex>,
+    pub(crate) bloom_filter: Option<BloomFilter>,
+    pub(crate) source: R,
+    /// Identifies the table within `block_cache`.
+    pub(crate) id: u64,
+    pub(crate) block_cache: Option<Arc<BlockCache>>,
+    _phantom: PhantomData<fn() -> TSSTable>,
+}
+
+impl<TSSTable, R> Table<TSSTable, R>
+    where TSSTable: SSTable, R: ReadAt {
+
+    pub fn open(source: R) -> io::Result<Self> {
+        let footer = Footer::read(&source)?;
+        let sections = footer.read_sections(&source)?;
+        let index_start = (footer.index_offset - footer.filter_offset) as usize;
+        let metadata_start = (footer.metadata_offset - footer.filter_offset) as usize;
+        let metadata = SSTableMetadata::deserialize(&sections[metadata_start..])?;
+        let index = Arc::new(SSTableIndex::deserialize(&sections[index_start..metadata_start])?);
+        let bloom_filter = if index_start > 0 {
+            Some(BloomFilter::deserialize(&sections[..index_start])?)
+        } else {
+            None
+        };
+        Ok(Table {
+            footer,
+            metadata,
+            index,
+            bloom_filter,
+            source,
+            id: block_cache::next_table_id(),
+            block_cache: None,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Caches the blocks decoded by point lookups and by the readers of the
+    /// table in `block_cache`, which may be shared with other tables.
+    ///
+    /// `verify` still reads the blocks from the source.
+    pub fn with_block_cache(mut self, block_cache: Arc<BlockCache>) -> Self {
+        self.block_cache = Some(block_cache);
+        self
+    }
+
+    #[cfg(feature = "mmap")]
+    /// Returns a copy of the table, which shares its source and its block
+    /// index, and whose blocks share the same entries of the block cache.
+    pub(crate) fn shallow_clone(&self) -> Self
+        where R: Clone {
+        Table {
+            footer: self.footer,
+            metadata: self.metadata.clone(),
+            index: self.index.clone(),
+            bloom_filter: self.bloom_filter.clone(),
+            source: self.source.clone(),
+            id: self.id,
+            block_cache: self.block_cache.clone(),
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn metadata(&self) -> &SSTableMetadata {
+        &self.metadata
+    }
+
+    pub fn index(&self) -> &SSTableIndex {
+        &self.index
+    }
+
+    /// Returns `false` if the sstable definitely does not contain `key`.
+    ///
+    /// Without a bloom filter, only the range of the keys is checked.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        may_contain(&self.metadata, self.bloom_filter.as_ref(), key)
+    }
+
+    /// Returns the decompressed block `block_id` and its length in the
+    /// sstable, or `None` past the last block.
+    ///
+    /// The block is taken from the block cache, if any, or added to it.
+    pub(crate) fn load_block(&self, block_id: usize) -> io::Result<Option<(Arc<[u8]>, u64)>> {
+        load_block(&self.index, &self.source, self.id, self.block_cache.as_deref(), block_id)
+    }
+
+    /// Returns a reader over the block `block_id` only.
+    ///
+    /// The block goes through the block cache, if any.
+    pub(crate) fn block_reader(&self, block_id: usize) -> Reader<'_, TSSTable::Reader> {
+        if self.block_cache.is_none() {
+            let num_bytes = self.index.blocks()[block_id].num_bytes;
+            return reader_at::<TSSTable, _>(&self.index, &self.source, block_id, num_bytes);
+        }
+        let block_reader = BlockReader::from_blocks(Box::new(move |next_block_id| {
+            if next_block_id == block_id {
+                self.load_block(block_id)
+            } else {
+                Ok(None)
+            }
+        }));
+        block_reader_at::<TSSTable>(&self.index, block_reader, block_id)
+    }
+
+    /// Returns a block reader over the `num_bytes` bytes of the source
+    /// starting at `offset`.
+    ///
+    /// With a block cache, the blocks go through the cache instead.
+    fn range_block_reader(&self, offset: u64, num_bytes: u64) -> BlockReader<'_> {
+        if self.block_cache.is_some() {
+            return BlockReader::from_blocks(Box::new(move |block_id| self.load_block(block_id)));
+        }
+        read_at::block_reader(&self.source, offset, num_bytes)
+    }
+
+    /// Returns the ordinal of `key`, i.e. its 0-based position in the
+    /// sstable, or `None` if the sstable does not contain `key`.
+    ///
+    /// At most one block is decoded.
+    pub fn key_to_ord(&self, key: &[u8]) -> io::Result<Option<u64>> {
+        let block_id = if let Some(block_id) = self.index.search(key) {
+            block_id
+        } else {
+            return Ok(None);
+        };
+        let mut reader = self.block_reader(block_id);
+        if reader.seek(key)? && reader.key() == key {
+            return Ok(Some(reader.ord()));
+        }
+        Ok(None)
+    }
+
+    /// Returns the key of ordinal `ord`, or `None` if `ord` is greater or
+    /// equal to the number of keys of the sstable.
+    ///
+    /// At most one block is decoded.
+    pub fn ord_to_key(&self, ord: u64) -> io::Result<Option<Vec<u8>>> {
+        if ord >= self.metadata.num_keys {
+            return Ok(None);
+        }
+        let block_id = if let Some(block_id) = self.index.search_ord(ord) {
+            block_id
+        } else {
+            return Ok(None);
+        };
+        let mut reader = self.block_reader(block_id);
+        reader.skip_to_restart_before_ord(ord)?;
+        while reader.advance()? {
+            if reader.ord() == ord {
+                return Ok(Some(reader.key().to_vec()));
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::InvalidData, "key ordinal is missing from its block"))
+    }
+
+    /// Returns a reader over the keys within `lower` and `upper`.
+    ///
+    /// Reading starts at the block that may contain the first key of the range.
+    pub fn range(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> io::Result<Reader<'_, TSSTable::Reader>> {
+        range_reader::<TSSTable, _>(&self.index, &self.footer,
+                                    |offset, num_bytes| Ok(self.range_block_reader(offset, num_bytes)),
+                                    lower, upper)
+    }
+
+    /// Returns a reader over the keys starting with `prefix`.
+    pub fn prefix(&self, prefix: &[u8]) -> io::Result<Reader<'_, TSSTable::Reader>> {
+        let mut reader = self.range(Bound::Included(prefix), Bound::Unbounded)?;
+        reader.set_prefix(prefix);
+        Ok(reader)
+    }
+
+    /// Same as `prefix`, but the reader takes ownership of the source.
+    pub fn into_prefix<'a>(self, prefix: &[u8]) -> io::Result<Reader<'a, TSSTable::Reader>>
+        where R: 'a {
+        let mut reader = self.into_range(Bound::Included(prefix), Bound::Unbounded)?;
+        reader.set_prefix(prefix);
+        Ok(reader)
+    }
+
+    /// Returns a reader going through the keys within `lower` and `upper`
+    /// in decreasing order.
+    pub fn reverse_range(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>)
+        -> io::Result<ReverseReader<'_, TSSTable, R>> {
+        Ok(ReverseReader::new(self, lower, upper))
+    }
+
+    /// Returns a reader going through the keys starting with `prefix`
+    /// in decreasing order.
+    pub fn reverse_prefix(&self, prefix: &[u8]) -> io::Result<ReverseReader<'_, TSSTable, R>> {
+        // All of the keys with the prefix are lower than the smallest key
+        // greater than the prefix that does not start with it.
+        let mut upper = prefix.to_vec();
+        while upper.last() == Some(&255u8) {
+            upper.pop();
+        }
+        let upper_bound = if let Some(last_byte) = upper.last_mut() {
+            *last_byte += 1;
+            Bound::Excluded(&upper[..])
+        } else {
+            Bound::Unbounded
+        };
+        Ok(ReverseReader::new(self, Bound::Included(prefix), upper_bound))
+    }
+
+    /// Returns a reader over the keys accepted by `automaton`.
+    pub fn search<A: Automaton>(&self, automaton: A) -> io::Result<SearchReader<'_, TSSTable::Reader, A>> {
+        let reader = self.range(Bound::Unbounded, Bound::Unbounded)?;
+        Ok(SearchReader::new(reader, automaton))
+    }
+
+    /// Same as `search`, but the reader takes ownership of the source.
+    pub fn into_search<'a, A: Automaton>(self, automaton: A) -> io::Result<SearchReader<'a, TSSTable::Reader, A>>
+        where R: 'a {
+        let reader = self.into_range(Bound::Unbounded, Bound::Unbounded)?;
+        Ok(SearchReader::new(reader, automaton))
+    }
+
+    /// Same as `range`, but the reader takes ownership of the source.
+    pub fn into_range<'a>(self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> io::Result<Reader<'a, TSSTable::Reader>>
+        where R: 'a {
+        if self.block_cache.is_some() {
+            let (index, source, id, block_cache) = (self.index, self.source, self.id, self.block_cache);
+            let load_index = index.clone();
+            let load_block = move |block_id| load_block(&load_index, &source, id, block_cache.as_deref(), block_id);
+            return range_reader::<TSSTable, _>(&index, &self.footer,
+                                               |_, _| Ok(BlockReader::from_blocks(Box::new(load_block))),
+                                               lower, upper);
+        }
+        let source = self.source;
+        range_reader::<TSSTable, _>(&self.index, &self.footer,
+                                    |offset, num_bytes| Ok(read_at::into_block_reader(source, offset, num_bytes)),
+                                    lower, upper)
+    }
+
+    /// Checks the checksums of all of the blocks, without decoding them.
+    ///
+    /// Corrupted blocks are reported as a `CorruptionError`. Blocks written
+    /// without checksum are only checked to be readable.
+    ///
+    /// The sections following the blocks (bloom filter, index, metadata and
+    /// footer) have already been checked when the table was opened.
+    pub fn verify(&self) -> io::Result<()> {
+        verify_blocks(read_at::block_reader(&self.source, 0, self.footer.filter_offset), &self.index)
+    }
+
+    /// Returns the value associated with `key`, if any.
+    ///
+    /// Only the block that may contain `key` is read and decoded, and
+    /// none if the bloom filter rules `key` out.
+    pub fn get(&self, key: &[u8]) -> io::Result<Option<<TSSTable::Value as ToOwned>::Owned>> {
+        if !self.may_contain(key) {
+            return Ok(None);
+        }
+        let block_id = if let Some(block_id) = self.index.search(key) {
+            block_id
+        } else {
+            return Ok(None);
+        };
+        let mut reader = self.block_reader(block_id);
+        if reader.seek(key)? && reader.key() == key {
+            return Ok(Some(reader.value().to_owned()));
+        }
+        Ok(None)
+    }
+}
+
+/// Returns `false` if the sstable described by `metadata` and `bloom_filter`
+/// definitely does not contain `key`.
+pub(crate) fn may_contain(metadata: &SSTableMetadata, bloom_filter: Option<&BloomFilter>, key: &[u8]) -> bool {
+    let in_range = match (&metadata.first_key, &metadata.last_key) {
+        (Some(first_key), Some(last_key)) => &first_key[..] <= key && key <= &last_key[..],
+        _ => false,
+    };
+    in_range && bloom_filter
+        .map(|bloom_filter| bloom_filter.may_contain(key))
+        .unwrap_or(true)
+}
+
+/// Checks the checksums of all of the blocks read by `block_reader`, and
+/// that there are as many of them as in `index`.
+pub(crate) fn verify_blocks(mut block_reader: BlockReader, index: &SSTableIndex) -> io::Result<()> {
+    let mut num_blocks = 0;
+    while block_reader.read_block()? {
+        num_blocks += 1;
+    }
+    if num_blocks != index.blocks().len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "number of blocks does not match the index"));
+    }
+    Ok(())
+}
+
+/// Returns the decompressed block `block_id` of the table `table_id` and its
+/// length in the sstable, or `None` past the last block.
+///
+/// The block is taken from `block_cache`, if any, or added to it.
+pub(crate) fn load_block<R: ReadAt + ?Sized>(
+    index: &SSTableIndex,
+    source: &R,
+    table_id: u64,
+    block_cache: Option<&BlockCache>,
+    block_id: usize) -> io::Result<Option<(Arc<[u8]>, u64)>> {
+    let block_meta = if let Some(block_meta) = index.blocks().get(block_id) {
+        block_meta
+    } else {
+        return Ok(None);
+    };
+    if let Some(block) = block_cache.and_then(|block_cache| block_cache.get(table_id, block_meta.offset)) {
+        return Ok(Some((block, block_meta.num_bytes)));
+    }
+    let mut block_reader = read_at::block_reader(source, block_meta.offset, block_meta.num_bytes);
+    block_reader.set_position(block_id, block_meta.offset);
+    if !block_reader.read_block()? {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "block is missing"));
+    }
+    let block: Arc<[u8]> = Arc::from(block_reader.buffer());
+    if let Some(block_cache) = block_cache {
+        block_cache.insert(table_id, block_meta.offset, block.clone());
+    }
+    Ok(Some((block, block_meta.num_bytes)))
+}
+
+/// Returns a reader positioned right before the first key of the block
+/// `block_id`, reading at most `num_bytes` bytes from `source`.
+pub(crate) fn reader_at<'a, TSSTable: SSTable, R: ReadAt + ?Sized>(
+    index: &SSTableIndex,
+    source: &'a R,
+    block_id: usize,
+    num_bytes: u64) -> Reader<'a, TSSTable::Reader> {
+    let block_reader = read_at::block_reader(source, index.blocks()[block_id].offset, num_bytes);
+    block_reader_at::<TSSTable>(index, block_reader, block_id)
+}
+
+/// Returns a reader positioned right before the first key of the block
+/// `block_id`, given a block reader starting at this block.
+pub(crate) fn block_reader_at<'a, TSSTable: SSTable>(
+    index: &SSTableIndex,
+    block_reader: BlockReader<'a>,
+    block_id: usize) -> Reader<'a, TSSTable::Reader> {
+    let block_meta = &index.blocks()[block_id];
+    let mut reader = TSSTable::reader_from_block_reader(block_reader);
+    reader.delta_reader.block_reader.set_position(block_id, block_meta.offset);
+    reader.set_next_ord(block_meta.first_ord);
+    reader
+}
+
+/// Returns a reader over the keys within `lower` and `upper`.
+///
+/// `open_block_reader` is called with the offset and the length of the
+/// bytes to read, which span from the first block of the range to the
+/// end of the blocks.
+pub(crate) fn range_reader<'a, TSSTable, F>(
+    index: &Arc<SSTableIndex>,
+    footer: &Footer,
+    open_block_reader: F,
+    lower: Bound<&[u8]>,
+    upper: Bound<&[u8]>) -> io::Result<Reader<'a, TSSTable::Reader>>
+    where TSSTable: SSTable, F: FnOnce(u64, u64) -> io::Result<BlockReader<'a>> {
+    let first_block_id = match lower {
+        Bound::Included(key) | Bound::Excluded(key) => index.search(key),
+        Bound::Unbounded if index.blocks().is_empty() => None,
+        Bound::Unbounded => Some(0),
+    };
+    let mut reader = if let Some(block_id) = first_block_id {
+        // The reader may go through all of the remaining blocks, up to the
+        // zero-length block ending them.
+        let offset = index.blocks()[block_id].offset;
+        let block_reader = open_block_reader(offset, footer.filter_offset - offset)?;
+        block_reader_at::<TSSTable>(index, block_reader, block_id)
+    } else {
+        let mut reader = TSSTable::reader_from_bytes(&[]);
+        reader.finish();
+        reader
+    };
+    reader.set_bounds(lower, upper);
+    reader.set_index(index.clone());
+    Ok(reader)
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+    use {SSTable, SSTableMetadata, VoidSSTable, U64SSTable, CorruptionError};
+    use {WriterOptions, BloomFilterOptions};
+    use std::ops::Bound;
+
+    /// Key of ordinal `i` in `small_block_u64_table`.
+    pub(crate) fn key(i: u64) -> String {
+        format!("key{:08}", i * 3)
+    }
+
+    /// Returns a `U64SSTable` associating `key(i)` with `i`, for `i` in
+    /// `0..20_000`, written in small blocks so that it has many of them.
+    pub(crate) fn small_block_u64_table() -> Vec<u8> {
+        let mut buffer = Vec::new();
+        {
+            let options = WriterOptions { block_size: 4_096, ..WriterOptions::default() };
+            let mut writer = U64SSTable::writer_with_options(&mut buffer, options);
+            for i in 0..20_000 {
+                writer.write(key(i).as_bytes(), &i).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        buffer
+    }
+
+    #[test]
+    fn test_table_get() {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = VoidSSTable::writer(&mut buffer);
+            for i in 0..200_000 {
+                writer.write(key(i).as_bytes(), &()).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        let table = VoidSSTable::table(Cursor::new(&buffer[..])).unwrap();
+        assert!(table.index().blocks().len() > 1);
+        for i in (0..200_000).step_by(997) {
+            assert_eq!(table.get(key(i).as_bytes()).unwrap(), Some(()));
+        }
+        assert_eq!(table.get(key(199_999).as_bytes()).unwrap(), Some(()));
+        assert_eq!(table.get(b"key00000001").unwrap(), None);
+        assert_eq!(table.get(b"a").unwrap(), None);
+        assert_eq!(table.get(b"key").unwrap(), None);
+        assert_eq!(table.get(b"zzz").unwrap(), None);
+    }
+
+    #[test]
+    fn test_table_bloom_filter() {
+        let bloom_filter = BloomFilterOptions { expected_num_keys: 200_000, false_positive_rate: 0.01 };
+        let options = WriterOptions { bloom_filter: Some(bloom_filter), ..WriterOptions::default() };
+        let mut buffer = Vec::new();
+        {
+            let mut writer = VoidSSTable::writer_with_options(&mut buffer, options);
+            for i in 0..200_000 {
+                writer.write(key(i).as_bytes(), &()).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        let table = VoidSSTable::table(Cursor::new(&buffer[..])).unwrap();
+        for i in 0..200_000 {
+            assert!(table.may_contain(key(i).as_bytes()));
+        }
+        let num_false_positives = (0..200_000)
+            .filter(|i| table.may_contain(format!("key{:08}", i * 3 + 1).as_bytes()))
+            .count();
+        assert!(num_false_positives < 4_000, "{} false positives", num_false_positives);
+        assert!(!table.may_contain(b"a"));
+        assert!(!table.may_contain(b"zzz"));
+        assert_eq!(table.get(key(1_234).as_bytes()).unwrap(), Some(()));
+        assert_eq!(table.get(b"key00000001").unwrap(), None);
+        assert!(table.verify().is_ok());
+        // Clearing bits of the filter would hide present keys.
+        let filter_offset = table.footer.filter_offset as usize;
+        let mut corrupted_buffer = buffer.clone();
+        for byte in &mut corrupted_buffer[filter_offset + 1..filter_offset + 100] {
+            *byte = 0u8;
+        }
+        let err = VoidSSTable::table(&corrupted_buffer[..]).err().unwrap();
+        assert_eq!(CorruptionError::from_io_error(&err).map(|err| err.block_id), Some(None));
+    }
+
+    fn aux_test_range(buffer: &[u8], lower: Bound<&[u8]>, upper: Bound<&[u8]>, expected: Vec<u64>) {
+        let table = VoidSSTable::table(Cursor::new(buffer)).unwrap();
+        let mut reader = table.range(lower, upper).unwrap();
+        let mut keys = Vec::new();
+        while reader.advance().unwrap() {
+            keys.push(reader.key().to_vec());
+        }
+        assert!(!reader.advance().unwrap());
+        let expected_keys: Vec<Vec<u8>> = expected.into_iter().map(|i| key(i).into_bytes()).collect();
+        assert_eq!(keys, expected_keys);
+    }
+
+    #[test]
+    fn test_table_range() {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = VoidSSTable::writer(&mut buffer);
+            for i in 0..200_000 {
+                writer.write(key(i).as_bytes(), &()).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        let k = |i: u64| key(i).into_bytes();
+        aux_test_range(&buffer[..], Bound::Included(&k(150_000)), Bound::Excluded(&k(150_003)),
+                       vec![150_000, 150_001, 150_002]);
+        aux_test_range(&buffer[..], Bound::Excluded(&k(150_000)), Bound::Included(&k(150_003)),
+                       vec![150_001, 150_002, 150_003]);
+        aux_test_range(&buffer[..], Bound::Included(b"key00450000a"), Bound::Included(b"key00450007"),
+                       vec![150_001, 150_002]);
+        aux_test_range(&buffer[..], Bound::Unbounded, Bound::Excluded(&k(3)), vec![0, 1, 2]);
+        aux_test_range(&buffer[..], Bound::Included(&k(199_998)), Bound::Unbounded, vec![199_998, 199_999]);
+        aux_test_range(&buffer[..], Bound::Included(b"zzz"), Bound::Unbounded, vec![]);
+        aux_test_range(&buffer[..], Bound::Included(&k(5)), Bound::Excluded(&k(5)), vec![]);
+        aux_test_range(&buffer[..], Bound::Unbounded, Bound::Excluded(b"a"), vec![]);
+        let table = VoidSSTable::table(Cursor::new(&buffer[..])).unwrap();
+        let block_meta = &table.index().blocks()[0];
+        let mut reader = VoidSSTable::range(Cursor::new(&buffer[..]),
+                                             Bound::Excluded(&block_meta.last_key[..]),
+                                             Bound::Unbounded).unwrap();
+        let mut num_keys = 0;
+        while reader.advance().unwrap() {
+            num_keys += 1;
+        }
+        assert!(num_keys > 0);
+        assert!(num_keys < 200_000);
+        let mut reader = VoidSSTable::range(Cursor::new(&buffer[..]), Bound::Unbounded, Bound::Unbounded).unwrap();
+        let mut num_keys = 0;
+        let buffer_range = buffer.as_ptr_range();
+        while reader.advance().unwrap() {
+            // Blocks are read in place from in-memory sources.
+            assert!(buffer_range.contains(&reader.delta_reader.block_reader.buffer().as_ptr()));
+            num_keys += 1;
+        }
+        assert_eq!(num_keys, 200_000);
+    }
+
+    fn prefix_keys(buffer: &[u8], prefix: &[u8]) -> Vec<Vec<u8>> {
+        let mut reader = VoidSSTable::prefix(Cursor::new(buffer), prefix).unwrap();
+        let mut keys = Vec::new();
+        while reader.advance().unwrap() {
+            keys.push(reader.key().to_vec());
+        }
+        keys
+    }
+
+    #[test]
+    fn test_table_prefix() {
+        let mut keys = Vec::new();
+        for user in 0..2_000 {
+            for item in 0..(user % 100) {
+                keys.push(format!("user/{}/{}", user, item).into_bytes());
+            }
+        }
+        keys.sort();
+        let mut buffer = Vec::new();
+        {
+            let options = WriterOptions { block_size: 1_024, ..WriterOptions::default() };
+            let mut writer = VoidSSTable::writer_with_options(&mut buffer, options);
+            for key in &keys {
+                writer.write(key, &()).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        assert!(VoidSSTable::table(Cursor::new(&buffer[..])).unwrap().index().blocks().len() > 100);
+        for prefix in &["user/42/", "user/199/", "user/1/", "user/4", "user/1999/", "user/2/", "user/100/", "", "zzz", "a"] {
+            let expected: Vec<Vec<u8>> = keys.iter()
+                .filter(|key| key.starts_with(prefix.as_bytes()))
+                .cloned()
+                .collect();
+            assert_eq!(prefix_keys(&buffer[..], prefix.as_bytes()), expected, "prefix {}", prefix);
+        }
+    }
+
+    #[test]
+    fn test_table_reverse() {
+        let buffer = small_block_u64_table();
+        let table = U64SSTable::table(Cursor::new(&buffer[..])).unwrap();
+        assert!(table.index().blocks().len() > 10);
+        let aux_test_reverse = |table: &::Table<U64SSTable, Cursor<&[u8]>>,
+                                lower: Bound<&[u8]>, upper: Bound<&[u8]>, expected: Vec<u64>| {
+            let mut reader = table.reverse_range(lower, upper).unwrap();
+            assert_eq!(reader.key(), b"");
+            assert_eq!(reader.value(), &0);
+            let mut vals = Vec::new();
+            while reader.advance().unwrap() {
+                assert_eq!(reader.key(), key(*reader.value()).as_bytes());
+                vals.push(*reader.value());
+            }
+            assert!(!reader.advance().unwrap());
+            assert_eq!(reader.key(), b"");
+            assert_eq!(reader.value(), &0);
+            assert_eq!(vals, expected);
+        };
+        aux_test_reverse(&table, Bound::Unbounded, Bound::Unbounded, (0..20_000).rev().collect());
+        aux_test_reverse(&table, Bound::Included(key(10).as_bytes()), Bound::Excluded(key(15).as_bytes()),
+                         vec![14, 13, 12, 11, 10]);
+        aux_test_reverse(&table, Bound::Excluded(key(10).as_bytes()), Bound::Included(key(15).as_bytes()),
+                         vec![15, 14, 13, 12, 11]);
+        aux_test_reverse(&table, Bound::Included(key(19_998).as_bytes()), Bound::Included(b"zzz"),
+                         vec![19_999, 19_998]);
+        aux_test_reverse(&table, Bound::Unbounded, Bound::Included(key(1).as_bytes()), vec![1, 0]);
+        aux_test_reverse(&table, Bound::Unbounded, Bound::Excluded(b"a"), vec![]);
+        let mut reader = table.reverse_prefix(b"key0004").unwrap();
+        let mut vals = Vec::new();
+        while reader.advance().unwrap() {
+            vals.push(*reader.value());
+        }
+        assert_eq!(vals, (13_334..16_667).rev().collect::<Vec<u64>>());
+    }
+
+    fn aux_test_seek<'a>(mut reader: ::Reader<'a, ::value::U64Reader>) {
+        assert!(reader.seek(key(10).as_bytes()).unwrap());
+        assert_eq!(*reader.value(), 10);
+        assert!(reader.seek(key(5).as_bytes()).unwrap());
+        assert_eq!(*reader.value(), 10);
+        assert!(reader.seek(b"key00000031").unwrap());
+        assert_eq!(*reader.value(), 11);
+        assert!(reader.advance().unwrap());
+        assert_eq!(*reader.value(), 12);
+        for &i in &[1_000, 1_001, 9_999, 10_000, 15_000, 19_999] {
+            assert!(reader.seek(key(i).as_bytes()).unwrap());
+            assert_eq!(reader.key(), key(i).as_bytes());
+            assert_eq!(*reader.value(), i);
+        }
+        assert!(!reader.seek(b"zzz").unwrap());
+        assert!(!reader.advance().unwrap());
+        assert!(!reader.seek(b"a").unwrap());
+    }
+
+    #[test]
+    fn test_table_seek() {
+        let buffer = small_block_u64_table();
+        let table = U64SSTable::table(Cursor::new(&buffer[..])).unwrap();
+        assert!(table.index().blocks().len() > 10);
+        aux_test_seek(table.range(Bound::Unbounded, Bound::Unbounded).unwrap());
+        aux_test_seek(U64SSTable::reader(&buffer[..]));
+        let mut reader = table.range(Bound::Included(key(3).as_bytes()), Bound::Excluded(key(15_000).as_bytes())).unwrap();
+        assert!(reader.seek(key(1).as_bytes()).unwrap());
+        assert_eq!(*reader.value(), 3);
+        assert!(reader.seek(key(14_999).as_bytes()).unwrap());
+        assert_eq!(*reader.value(), 14_999);
+        assert!(!reader.seek(key(15_000).as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn test_table_ord() {
+        let buffer = small_block_u64_table();
+        let table = U64SSTable::table(Cursor::new(&buffer[..])).unwrap();
+        assert!(table.index().blocks().len() > 10);
+        for block_meta in table.index().blocks().to_vec() {
+            assert_eq!(table.key_to_ord(&block_meta.last_key).unwrap().map(|ord| key(ord).into_bytes()),
+                       Some(block_meta.last_key.clone()));
+            assert_eq!(table.ord_to_key(block_meta.first_ord).unwrap(), Some(key(block_meta.first_ord).into_bytes()));
+        }
+        for i in (0..20_000).step_by(97).chain(Some(19_999)) {
+            assert_eq!(table.key_to_ord(key(i).as_bytes()).unwrap(), Some(i));
+            assert_eq!(table.ord_to_key(i).unwrap(), Some(key(i).into_bytes()));
+        }
+        assert_eq!(table.key_to_ord(b"key00000001").unwrap(), None);
+        assert_eq!(table.key_to_ord(b"zzz").unwrap(), None);
+        assert_eq!(table.ord_to_key(20_000).unwrap(), None);
+        let mut reader = U64SSTable::reader(&buffer[..]);
+        while reader.advance().unwrap() {
+            assert_eq!(reader.ord(), *reader.value());
+        }
+        let mut reader = table.range(Bound::Included(key(1_000).as_bytes()), Bound::Unbounded).unwrap();
+        assert!(reader.advance().unwrap());
+        assert_eq!(reader.ord(), 1_000);
+        assert!(reader.seek(key(15_000).as_bytes()).unwrap());
+        assert_eq!(reader.ord(), 15_000);
+        assert!(reader.advance().unwrap());
+        assert_eq!(reader.ord(), 15_001);
+    }
+
+    #[test]
+    fn test_table_get_empty() {
+        let mut buffer = Vec::new();
+        VoidSSTable::writer(&mut buffer).finalize().unwrap();
+        assert_eq!(VoidSSTable::get(Cursor::new(&buffer[..]), b"abc").unwrap(), None);
+        let table = VoidSSTable::table(Cursor::new(&buffer[..])).unwrap();
+        assert_eq!(table.metadata(), &SSTableMetadata::default());
+        let mut reader = VoidSSTable::range(Cursor::new(&buffer[..]), Bound::Unbounded, Bound::Unbounded).unwrap();
+        assert!(!reader.advance().unwrap());
+    }
+
+    #[test]
+    fn test_table_metadata() {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = VoidSSTable::writer(&mut buffer);
+            writer.set_property("segment", "17");
+            for i in 0..200_000 {
+                writer.write(key(i).as_bytes(), &()).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        let metadata = SSTableMetadata::read(&buffer[..]).unwrap();
+        assert_eq!(metadata.num_keys, 200_000);
+        assert!(metadata.num_blocks > 1);
+        assert_eq!(metadata.first_key, Some(key(0).into_bytes()));
+        assert_eq!(metadata.last_key, Some(key(199_999).into_bytes()));
+        assert_eq!(metadata.properties.get("segment").map(|val| &val[..]), Some("17"));
+        let table = VoidSSTable::table(Cursor::new(&buffer[..])).unwrap();
+        assert_eq!(table.metadata(), &metadata);
+        assert_eq!(table.index().blocks().len() as u64, metadata.num_blocks);
+        // The metadata is read without the index, and has its own checksum.
+        let index_offset = table.footer.index_offset as usize;
+        let metadata_offset = table.footer.metadata_offset as usize;
+        let mut corrupted_buffer = buffer.clone();
+        corrupted_buffer[index_offset] ^= 1u8;
+        assert_eq!(SSTableMetadata::read(&corrupted_buffer[..]).unwrap(), metadata);
+        assert!(VoidSSTable::table(&corrupted_buffer[..]).is_err());
+        let mut corrupted_buffer = buffer.clone();
+        corrupted_buffer[metadata_offset] ^= 1u8;
+        let err = SSTableMetadata::read(&corrupted_buffer[..]).unwrap_err();
+        assert!(CorruptionError::from_io_error(&err).is_some());
+    }
+
+    #[test]
+    fn test_table_corruption() {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = VoidSSTable::writer(&mut buffer);
+            for i in 0..200_000 {
+                writer.write(key(i).as_bytes(), &()).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        assert!(VoidSSTable::verify(Cursor::new(&buffer[..])).is_ok());
+        let (block_offset, block_len) = {
+            let table = VoidSSTable::table(Cursor::new(&buffer[..])).unwrap();
+            let block_meta = &table.index().blocks()[1];
+            (block_meta.offset, block_meta.num_bytes)
+        };
+        buffer[(block_offset + block_len / 2) as usize] ^= 1u8;
+        let expected_err = CorruptionError { block_id: Some(1), offset: block_offset };
+        let err = VoidSSTable::verify(Cursor::new(&buffer[..])).unwrap_err();
+        assert_eq!(CorruptionError::from_io_error(&err), Some(&expected_err));
+        let table = VoidSSTable::table(Cursor::new(&buffer[..])).unwrap();
+        assert!(table.get(key(0).as_bytes()).is_ok());
+        let err = table.get(&table.index().blocks()[1].last_key.clone()).unwrap_err();
+        assert_eq!(CorruptionError::from_io_error(&err), Some(&expected_err));
+        let mut reader = VoidSSTable::reader(&buffer[..]);
+        let err = loop {
+            match reader.advance() {
+                Ok(has_key) => assert!(has_key),
+                Err(err) => break err,
+            }
+        };
+        assert_eq!(CorruptionError::from_io_error(&err), Some(&expected_err));
+    }
+
+    #[test]
+    fn test_table_sections_corruption() {
+        let bloom_filter = BloomFilterOptions { expected_num_keys: 1_000, false_positive_rate: 0.01 };
+        let options = WriterOptions { bloom_filter: Some(bloom_filter), ..WriterOptions::default() };
+        let mut buffer = Vec::new();
+        {
+            let mut writer = VoidSSTable::writer_with_options(&mut buffer, options);
+            for i in 0..1_000 {
+                writer.write(key(i).as_bytes(), &()).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        let end_of_blocks = VoidSSTable::table(&buffer[..]).unwrap().footer.filter_offset - 4;
+        for byte in end_of_blocks as usize..buffer.len() {
+            for bit in 0..8 {
+                buffer[byte] ^= 1u8 << bit;
+                assert!(VoidSSTable::table(&buffer[..]).is_err(), "bit {} of byte {}", bit, byte);
+                assert!(VoidSSTable::verify(&buffer[..]).is_err());
+                buffer[byte] ^= 1u8 << bit;
+            }
+        }
+        let expected_err = CorruptionError { block_id: None, offset: end_of_blocks };
+        buffer[end_of_blocks as usize + 10] ^= 1u8;
+        let err = VoidSSTable::get(&buffer[..], key(10).as_bytes()).unwrap_err();
+        assert_eq!(CorruptionError::from_io_error(&err), Some(&expected_err));
+    }
+
+    #[test]
+    fn test_table_invalid() {
+        assert!(VoidSSTable::table(Cursor::new(&[1u8, 2u8][..])).is_err());
+        let mut buffer = Vec::new();
+        VoidSSTable::writer(&mut buffer).finalize().unwrap();
+        assert!(VoidSSTable::table(Cursor::new(&buffer[..buffer.len() - 1])).is_err());
+        assert!(VoidSSTable::table(Cursor::new(&buffer[1..])).is_err());
+        assert!(VoidSSTable::table(Cursor::new(&[0u8; 100][..])).is_err());
+    }
+}
@@ -1,20 +1,68 @@
+use std::convert::TryFrom;
 use std::io;
-use BlockReader;
+use vint;
+use bitpacker;
+#[cfg(feature = "serde")]
+use std::marker::PhantomData;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "serde")]
+use bincode::{self, Options};
 
 pub trait ValueReader: Default {
 
-    type Value;
+    type Value: ?Sized;
 
-    fn value(&self) -> &Self::Value;
+    /// Returns the value decoded by the last call to `read`.
+    ///
+    /// `data` is the slice that was passed to `read`, so that values
+    /// can be borrowed from the block without being copied.
+    fn value<'b>(&'b self, data: &'b [u8]) -> &'b Self::Value;
 
-    fn read(&mut self, reader: &mut BlockReader) -> io::Result<()>;
+    /// Decodes the value located at the beginning of `data`.
+    ///
+    /// `data` is the remainder of the current block, starting right
+    /// after the key. Returns the number of bytes consumed.
+    fn read(&mut self, data: &[u8]) -> io::Result<usize>;
+
+    /// Called when a new block is loaded, before reading its first value.
+    ///
+    /// `block` is the whole block. Readers keeping state from one value to
+    /// the next must reset it here. Returns the number of bytes of the
+    /// section written by `ValueWriter::serialize_block` at the start of the block.
+    fn load_block(&mut self, _block: &[u8]) -> io::Result<usize> {
+        Ok(0)
+    }
+
+    /// Called before reading the value of a restart point, i.e. an entry
+    /// the reader may jump to without decoding the previous ones.
+    ///
+    /// `entry_idx` is the position of the entry within the block. Readers
+    /// keeping state from one value to the next must reset it here, as
+    /// `ValueWriter::restart` does.
+    fn restart(&mut self, _entry_idx: usize) {}
 }
 
 pub trait ValueWriter: Default {
 
-    type Value;
+    type Value: ?Sized;
 
     fn write(&mut self, val: &Self::Value, writer: &mut Vec<u8>);
+
+    /// Called when a block is flushed.
+    ///
+    /// Whatever is written to `output` is stored at the start of the block,
+    /// before its first key. Writers keeping state from one value to the next
+    /// must reset it here, so that every block can be decoded independently.
+    fn serialize_block(&mut self, _output: &mut Vec<u8>) {}
+
+    /// Called before writing the value of a restart point.
+    ///
+    /// Writers keeping state from one value to the next must reset it here,
+    /// so that the value can be decoded without the previous ones.
+    fn restart(&mut self) {}
 }
 
 
@@ -24,12 +72,12 @@ pub struct VoidReader;
 impl ValueReader for VoidReader {
     type Value = ();
 
-    fn value(&self) -> &Self::Value {
+    fn value<'b>(&'b self, _data: &'b [u8]) -> &'b Self::Value {
         &()
     }
 
-    fn read(&mut self, _reader: &mut BlockReader) -> io::Result<()> {
-        Ok(())
+    fn read(&mut self, _data: &[u8]) -> io::Result<usize> {
+        Ok(0)
     }
 }
 
@@ -40,4 +88,438 @@ impl ValueWriter for VoidWriter {
     type Value = ();
 
     fn write(&mut self, _: &Self::Value, _: &mut Vec<u8>) {}
-}
\ No newline at end of file
+}
+
+
+#[derive(Default)]
+pub struct U64Reader(u64);
+
+impl ValueReader for U64Reader {
+    type Value = u64;
+
+    fn value<'b>(&'b self, _data: &'b [u8]) -> &'b Self::Value {
+        &self.0
+    }
+
+    fn read(&mut self, data: &[u8]) -> io::Result<usize> {
+        let (num_bytes, val) = vint::deserialize_read(data);
+        self.0 = val;
+        Ok(num_bytes)
+    }
+}
+
+#[derive(Default)]
+pub struct U64Writer;
+
+impl ValueWriter for U64Writer {
+    type Value = u64;
+
+    fn write(&mut self, val: &Self::Value, writer: &mut Vec<u8>) {
+        vint::serialize_into_vec(*val, writer);
+    }
+}
+
+
+/// Reads length-prefixed byte slices, borrowing them directly
+/// from the block.
+#[derive(Default)]
+pub struct BytesReader {
+    start: usize,
+    stop: usize,
+}
+
+impl ValueReader for BytesReader {
+    type Value = [u8];
+
+    fn value<'b>(&'b self, data: &'b [u8]) -> &'b Self::Value {
+        &data[self.start..self.stop]
+    }
+
+    fn read(&mut self, data: &[u8]) -> io::Result<usize> {
+        let (num_bytes, len) = vint::deserialize_read(data);
+        if len > (data.len() - num_bytes) as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "value exceeds the block"));
+        }
+        self.start = num_bytes;
+        self.stop = num_bytes + len as usize;
+        Ok(self.stop)
+    }
+}
+
+#[derive(Default)]
+pub struct BytesWriter;
+
+impl ValueWriter for BytesWriter {
+    type Value = [u8];
+
+    fn write(&mut self, val: &Self::Value, writer: &mut Vec<u8>) {
+        vint::serialize_into_vec(val.len() as u64, writer);
+        writer.extend_from_slice(val);
+    }
+}
+
+
+/// Reads `u64` values stored as a bit-packed column at the start
+/// of each block.
+///
+/// The whole column is decoded when the block is loaded.
+#[derive(Default)]
+pub struct BitpackedU64Reader {
+    vals: Vec<u64>,
+    cursor: usize,
+}
+
+impl ValueReader for BitpackedU64Reader {
+    type Value = u64;
+
+    fn value<'b>(&'b self, _data: &'b [u8]) -> &'b Self::Value {
+        self.vals.get(self.cursor.wrapping_sub(1)).unwrap_or(&0)
+    }
+
+    fn read(&mut self, _data: &[u8]) -> io::Result<usize> {
+        if self.cursor >= self.vals.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "block has more keys than values"));
+        }
+        self.cursor += 1;
+        Ok(0)
+    }
+
+    fn load_block(&mut self, block: &[u8]) -> io::Result<usize> {
+        let invalid_column = || io::Error::new(io::ErrorKind::InvalidData, "invalid value column");
+        let mut data = block;
+        let num_vals = vint::read_u64(&mut data)?;
+        let min_val = vint::read_u64(&mut data)?;
+        let (&num_bits, data) = data.split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "truncated value column"))?;
+        // Every key takes at least one byte of the block, even when values take none.
+        if num_bits > 64 || num_vals > data.len() as u64 {
+            return Err(invalid_column());
+        }
+        let num_vals = num_vals as usize;
+        let column_num_bytes = bitpacker::num_bytes(num_vals, num_bits)
+            .filter(|&column_num_bytes| column_num_bytes <= data.len())
+            .ok_or_else(invalid_column)?;
+        self.vals.clear();
+        bitpacker::unpack(data, num_vals, num_bits, &mut self.vals);
+        for val in &mut self.vals {
+            *val = val.checked_add(min_val).ok_or_else(invalid_column)?;
+        }
+        self.cursor = 0;
+        Ok(block.len() - data.len() + column_num_bytes)
+    }
+
+    fn restart(&mut self, entry_idx: usize) {
+        self.cursor = entry_idx;
+    }
+}
+
+/// Writes `u64` values as a bit-packed column at the start of each block.
+///
+/// The column header records the number of values, their minimum
+/// and the bit width of their difference to the minimum.
+#[derive(Default)]
+pub struct BitpackedU64Writer {
+    vals: Vec<u64>,
+}
+
+impl ValueWriter for BitpackedU64Writer {
+    type Value = u64;
+
+    fn write(&mut self, val: &Self::Value, _writer: &mut Vec<u8>) {
+        self.vals.push(*val);
+    }
+
+    fn serialize_block(&mut self, output: &mut Vec<u8>) {
+        let min_val = self.vals.iter().cloned().min().unwrap_or(0u64);
+        let max_val = self.vals.iter().cloned().max().unwrap_or(0u64);
+        let num_bits = bitpacker::num_bits(max_val - min_val);
+        for val in &mut self.vals {
+            *val -= min_val;
+        }
+        vint::serialize_into_vec(self.vals.len() as u64, output);
+        vint::serialize_into_vec(min_val, output);
+        output.push(num_bits);
+        bitpacker::pack(&self.vals[..], num_bits, output);
+        self.vals.clear();
+    }
+}
+
+/// Information associated to a term in a term dictionary.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TermInfo {
+    pub doc_freq: u32,
+    pub postings_offset: u64,
+    pub positions_offset: u64,
+}
+
+/// Reads `TermInfo` values.
+///
+/// Offsets are delta-encoded against the previous value of the block,
+/// except at restart points.
+#[derive(Default)]
+pub struct TermInfoReader {
+    term_info: TermInfo,
+}
+
+impl ValueReader for TermInfoReader {
+    type Value = TermInfo;
+
+    fn value<'b>(&'b self, _data: &'b [u8]) -> &'b Self::Value {
+        &self.term_info
+    }
+
+    fn read(&mut self, data: &[u8]) -> io::Result<usize> {
+        let (mut num_bytes, doc_freq) = vint::deserialize_read(data);
+        let (postings_num_bytes, postings_delta) = vint::deserialize_read(&data[num_bytes..]);
+        num_bytes += postings_num_bytes;
+        let (positions_num_bytes, positions_delta) = vint::deserialize_read(&data[num_bytes..]);
+        num_bytes += positions_num_bytes;
+        let invalid_term_info = || io::Error::new(io::ErrorKind::InvalidData, "invalid term info");
+        self.term_info.doc_freq = u32::try_from(doc_freq).map_err(|_| invalid_term_info())?;
+        self.term_info.postings_offset = self.term_info.postings_offset.checked_add(postings_delta)
+            .ok_or_else(invalid_term_info)?;
+        self.term_info.positions_offset = self.term_info.positions_offset.checked_add(positions_delta)
+            .ok_or_else(invalid_term_info)?;
+        Ok(num_bytes)
+    }
+
+    fn load_block(&mut self, _block: &[u8]) -> io::Result<usize> {
+        self.term_info = TermInfo::default();
+        Ok(0)
+    }
+
+    fn restart(&mut self, _entry_idx: usize) {
+        self.term_info = TermInfo::default();
+    }
+}
+
+/// Writes `TermInfo` values.
+///
+/// `postings_offset` and `positions_offset` are expected to be increasing.
+#[derive(Default)]
+pub struct TermInfoWriter {
+    previous: TermInfo,
+}
+
+impl ValueWriter for TermInfoWriter {
+    type Value = TermInfo;
+
+    fn write(&mut self, val: &Self::Value, writer: &mut Vec<u8>) {
+        assert!(val.postings_offset >= self.previous.postings_offset &&
+                    val.positions_offset >= self.previous.positions_offset,
+                "Term info offsets should be increasing. ({:?} > {:?})", self.previous, val);
+        vint::serialize_into_vec(u64::from(val.doc_freq), writer);
+        vint::serialize_into_vec(val.postings_offset - self.previous.postings_offset, writer);
+        vint::serialize_into_vec(val.positions_offset - self.previous.positions_offset, writer);
+        self.previous = val.clone();
+    }
+
+    fn serialize_block(&mut self, _output: &mut Vec<u8>) {
+        self.previous = TermInfo::default();
+    }
+
+    fn restart(&mut self) {
+        self.previous = TermInfo::default();
+    }
+}
+
+/// Reads values serialized with bincode's varint encoding.
+///
+/// `value` returns `T::default()` if no value has been read yet.
+#[cfg(feature = "serde")]
+#[derive(Default)]
+pub struct SerdeReader<T> {
+    value: T,
+}
+
+#[cfg(feature = "serde")]
+impl<T: DeserializeOwned + Default> ValueReader for SerdeReader<T> {
+    type Value = T;
+
+    fn value<'b>(&'b self, _data: &'b [u8]) -> &'b Self::Value {
+        &self.value
+    }
+
+    fn read(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut cursor = data;
+        let value = bincode::DefaultOptions::new()
+            .deserialize_from(&mut cursor)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.value = value;
+        Ok(data.len() - cursor.len())
+    }
+}
+
+/// Writes values with bincode's varint encoding.
+///
+/// `write` panics if bincode fails to serialize the value.
+#[cfg(feature = "serde")]
+pub struct SerdeWriter<T>(PhantomData<T>);
+
+#[cfg(feature = "serde")]
+impl<T> Default for SerdeWriter<T> {
+    fn default() -> Self {
+        SerdeWriter(PhantomData)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Serialize> ValueWriter for SerdeWriter<T> {
+    type Value = T;
+
+    fn write(&mut self, val: &Self::Value, writer: &mut Vec<u8>) {
+        bincode::DefaultOptions::new()
+            .serialize_into(writer, val)
+            .expect("Failed to serialize value");
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use vint;
+    use bitpacker;
+    use super::{ValueReader, ValueWriter, U64Reader, U64Writer, BytesReader, BytesWriter};
+    use super::{TermInfo, TermInfoReader, TermInfoWriter};
+    use super::{BitpackedU64Reader, BitpackedU64Writer};
+
+    #[test]
+    fn test_u64_value() {
+        let vals = [0u64, 1u64, 127u64, 128u64, 1_000_000u64, u64::MAX];
+        let mut buffer = Vec::new();
+        let mut writer = U64Writer;
+        for val in &vals {
+            writer.write(val, &mut buffer);
+        }
+        let mut reader = U64Reader::default();
+        let mut offset = 0;
+        for val in &vals {
+            let num_bytes = reader.read(&buffer[offset..]).unwrap();
+            assert_eq!(reader.value(&buffer[offset..]), val);
+            offset += num_bytes;
+        }
+        assert_eq!(offset, buffer.len());
+    }
+
+    #[test]
+    fn test_bytes_value() {
+        let long_val = vec![3u8; 300];
+        let vals: [&[u8]; 3] = [b"", b"abc", &long_val[..]];
+        let mut buffer = Vec::new();
+        let mut writer = BytesWriter;
+        for val in &vals {
+            writer.write(val, &mut buffer);
+        }
+        let mut reader = BytesReader::default();
+        let mut offset = 0;
+        for val in &vals {
+            let num_bytes = reader.read(&buffer[offset..]).unwrap();
+            assert_eq!(reader.value(&buffer[offset..]), *val);
+            offset += num_bytes;
+        }
+        assert_eq!(offset, buffer.len());
+        assert!(reader.read(&[5u8, 1u8, 2u8]).is_err());
+    }
+
+    #[test]
+    fn test_term_info_value() {
+        let term_info = |doc_freq: u32, postings_offset: u64, positions_offset: u64| TermInfo {
+            doc_freq, postings_offset, positions_offset
+        };
+        let vals = [term_info(3, 1_000, 2_000), term_info(1, 1_010, 2_000), term_info(200, 5_000_000, 7_000_000)];
+        let mut buffer = Vec::new();
+        let mut writer = TermInfoWriter::default();
+        for val in &vals {
+            writer.write(val, &mut buffer);
+        }
+        let block_len = buffer.len();
+        writer.serialize_block(&mut Vec::new());
+        writer.write(&vals[1], &mut buffer);
+        let mut reader = TermInfoReader::default();
+        let mut offset = 0;
+        for val in &vals {
+            let num_bytes = reader.read(&buffer[offset..]).unwrap();
+            assert_eq!(reader.value(&buffer[offset..]), val);
+            offset += num_bytes;
+        }
+        assert_eq!(offset, block_len);
+        assert_eq!(reader.load_block(&buffer[offset..]).unwrap(), 0);
+        reader.read(&buffer[offset..]).unwrap();
+        assert_eq!(reader.value(&buffer[offset..]), &vals[1]);
+    }
+
+    #[test]
+    fn test_term_info_invalid_value() {
+        let mut reader = TermInfoReader::default();
+        let mut buffer = Vec::new();
+        for &val in &[u64::from(u32::MAX) + 1, 0, 0] {
+            vint::serialize_into_vec(val, &mut buffer);
+        }
+        assert!(reader.read(&buffer[..]).is_err());
+        let mut buffer = Vec::new();
+        for &val in &[1, u64::MAX, 0, 1, 1, 0] {
+            vint::serialize_into_vec(val, &mut buffer);
+        }
+        let num_bytes = reader.read(&buffer[..]).unwrap();
+        assert_eq!(reader.value(&buffer[..]).postings_offset, u64::MAX);
+        assert!(reader.read(&buffer[num_bytes..]).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_term_info_non_increasing_offsets() {
+        let mut writer = TermInfoWriter::default();
+        let mut buffer = Vec::new();
+        writer.write(&TermInfo { doc_freq: 1, postings_offset: 10, positions_offset: 0 }, &mut buffer);
+        writer.write(&TermInfo { doc_freq: 1, postings_offset: 9, positions_offset: 0 }, &mut buffer);
+    }
+
+    #[test]
+    fn test_bitpacked_u64_value() {
+        let vals = [1_000u64, 1_003u64, 1_000_000u64, 1_001u64];
+        let mut block = Vec::new();
+        let mut writer = BitpackedU64Writer::default();
+        for val in &vals {
+            writer.write(val, &mut block);
+        }
+        assert!(block.is_empty());
+        writer.serialize_block(&mut block);
+        // num vals, min val (2 bytes), num bits, 4 * 20 bits
+        assert_eq!(block.len(), 1 + 2 + 1 + 10);
+        block.extend_from_slice(b"keys");
+        let mut reader = BitpackedU64Reader::default();
+        assert_eq!(reader.value(&block[..]), &0);
+        let num_bytes = reader.load_block(&block[..]).unwrap();
+        assert_eq!(&block[num_bytes..], b"keys");
+        for val in &vals {
+            assert_eq!(reader.read(&block[num_bytes..]).unwrap(), 0);
+            assert_eq!(reader.value(&block[num_bytes..]), val);
+        }
+        assert!(reader.read(&block[num_bytes..]).is_err());
+        assert!(reader.load_block(&block[..4]).is_err());
+    }
+
+    fn bitpacked_column(num_vals: u64, min_val: u64, num_bits: u8, vals: &[u64]) -> Vec<u8> {
+        let mut block = Vec::new();
+        vint::serialize_into_vec(num_vals, &mut block);
+        vint::serialize_into_vec(min_val, &mut block);
+        block.push(num_bits);
+        bitpacker::pack(vals, num_bits, &mut block);
+        block.extend_from_slice(b"keys");
+        block
+    }
+
+    #[test]
+    fn test_bitpacked_u64_invalid_column() {
+        let mut reader = BitpackedU64Reader::default();
+        assert_eq!(reader.load_block(&bitpacked_column(2, 7, 3, &[0, 5])).unwrap(), 4);
+        assert_eq!(reader.load_block(&bitpacked_column(4, 7, 0, &[])).unwrap(), 3);
+        assert!(reader.load_block(&bitpacked_column(5, 7, 0, &[])).is_err());
+        assert!(reader.load_block(&bitpacked_column(u64::MAX, 7, 0, &[])).is_err());
+        assert!(reader.load_block(&bitpacked_column(2, u64::MAX - 1, 3, &[0, 5])).is_err());
+        let mut block = bitpacked_column(1, 7, 8, &[3]);
+        block[2] = 65;
+        assert!(reader.load_block(&block).is_err());
+        assert!(reader.load_block(&[0x80u8]).is_err());
+    }
+}
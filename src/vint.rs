@@ -1,9 +1,11 @@
+use std::io;
+
 const CONTINUE_BIT: u8 = 128u8;
 
 pub fn serialize(mut val: u64, buffer: &mut [u8]) -> usize {
     for (i, b) in buffer.iter_mut().enumerate() {
         let next_byte: u8 = (val & 127u64) as u8;
-        val = val >> 7;
+        val >>= 7;
         if val == 0u64 {
             *b = next_byte;
             return i + 1;
@@ -14,6 +16,12 @@ pub fn serialize(mut val: u64, buffer: &mut [u8]) -> usize {
     10 //< actually unreachable
 }
 
+pub fn serialize_into_vec(val: u64, output: &mut Vec<u8>) {
+    let mut buffer = [0u8; 10];
+    let len = serialize(val, &mut buffer[..]);
+    output.extend_from_slice(&buffer[..len]);
+}
+
 // super slow but we don't care
 pub fn deserialize_read(buf: &[u8]) -> (usize, u64) {
     let mut result = 0u64;
@@ -32,11 +40,48 @@ pub fn deserialize_read(buf: &[u8]) -> (usize, u64) {
 }
 
 
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated data")
+}
+
+/// Reads a vint at the beginning of `data`, and advances `data` past it.
+///
+/// Unlike `deserialize_read`, this checks that the vint is complete.
+pub fn read_u64(data: &mut &[u8]) -> io::Result<u64> {
+    if data.is_empty() {
+        return Err(truncated());
+    }
+    let (consumed, val) = deserialize_read(data);
+    if data[consumed - 1] >= CONTINUE_BIT {
+        return Err(truncated());
+    }
+    *data = &data[consumed..];
+    Ok(val)
+}
+
+/// Reads a vint-length-prefixed byte slice, and advances `data` past it.
+pub fn read_bytes<'a>(data: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+    let len = read_u64(data)?;
+    if len > data.len() as u64 {
+        return Err(truncated());
+    }
+    let (bytes, remaining) = data.split_at(len as usize);
+    *data = remaining;
+    Ok(bytes)
+}
+
+/// Writes a vint-length-prefixed byte slice.
+pub fn write_bytes(bytes: &[u8], output: &mut Vec<u8>) {
+    serialize_into_vec(bytes.len() as u64, output);
+    output.extend_from_slice(bytes);
+}
+
+
 #[cfg(test)]
 mod tests {
     use vint::serialize;
     use vint::deserialize_read;
-    use std::u64;
+    use vint::{read_u64, read_bytes, write_bytes, serialize_into_vec};
 
     fn aux_test_int(val: u64, expect_len: usize) {
         let mut buffer = [0u8; 14];
@@ -59,5 +104,28 @@ mod tests {
         }
         aux_test_int(u64::MAX, 10);
     }
-}
 
+    #[test]
+    fn test_read_u64() {
+        let mut buffer = Vec::new();
+        serialize_into_vec(300, &mut buffer);
+        serialize_into_vec(2, &mut buffer);
+        let mut data = &buffer[..];
+        assert_eq!(read_u64(&mut data).unwrap(), 300);
+        assert_eq!(read_u64(&mut data).unwrap(), 2);
+        assert!(read_u64(&mut data).is_err());
+        assert!(read_u64(&mut &buffer[..1]).is_err());
+    }
+
+    #[test]
+    fn test_read_bytes() {
+        let mut buffer = Vec::new();
+        write_bytes(b"abc", &mut buffer);
+        write_bytes(b"", &mut buffer);
+        let mut data = &buffer[..];
+        assert_eq!(read_bytes(&mut data).unwrap(), b"abc");
+        assert_eq!(read_bytes(&mut data).unwrap(), b"");
+        assert!(data.is_empty());
+        assert!(read_bytes(&mut &buffer[..3]).is_err());
+    }
+}